@@ -2,7 +2,7 @@
 
 use std::{
     collections::VecDeque,
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
     sync::{
         atomic::{AtomicBool, Ordering},
         Once,
@@ -10,7 +10,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use bitcode::encode;
+use bitcode::{decode, encode};
 use cakap2::{
     packet::{Action, ReliableIndex},
     Event, PeerStateMachine, RecommendedAction,
@@ -20,14 +20,18 @@ use godot::{
     classes::{image::Format, Engine, Image},
     prelude::*,
 };
+use record::{Record, Recorder};
 use stream::camera_streaming;
 use tasker::shared::{OwnedData, SharedDataReceiver};
 
+mod record;
 mod stream;
 mod urdf;
 
 const STREAM_WIDTH: u32 = 1920;
 const STREAM_HEIGHT: u32 = 720;
+/// The number of redundant unreliable soft stops sent by `LunabotConn::emergency_stop`.
+const EMERGENCY_STOP_UNRELIABLE_COUNT: usize = 5;
 
 struct LunabaseLib;
 
@@ -75,12 +79,32 @@ pub fn init_panic_hook() {
     });
 }
 
+/// When to ask lunabot to clear its set of received reliable packets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReconnectionStrategy {
+    /// Once, when the first packet from lunabot is received.
+    OnFirstPacket,
+    /// On the first packet, and then every given duration while lunabot is known.
+    Periodic(Duration),
+    /// On the first packet, and again on the first packet after lunabot has been silent for the
+    /// given duration.
+    OnPeerTimeout(Duration),
+}
+
 struct LunabotConnInner {
     cakap_sm: PeerStateMachine,
     udp: UdpSocket,
     to_lunabot: VecDeque<Action>,
     bitcode_buffer: bitcode::Buffer,
     did_reconnection: bool,
+    reconnection_strategy: ReconnectionStrategy,
+    last_reconnection_at: Instant,
+    reconnection_index: Option<ReliableIndex>,
+    recorder: Option<Recorder>,
+    replay: VecDeque<Record>,
+    replay_start: Instant,
+    /// Whether there were reliable packets pending at the end of the last frame.
+    had_pending_reliable: bool,
     last_steering: Option<(Steering, ReliableIndex)>,
     send_to: Option<SocketAddr>,
     stream_lendee: SharedDataReceiver<Vec<u8>>,
@@ -96,6 +120,11 @@ struct LunabotConn {
     stream_image: Gd<Image>,
     #[var]
     stream_image_updated: bool,
+    /// The smoothed round trip time to lunabot in milliseconds, as estimated by cakap from the
+    /// acknowledgements of reliable packets. This is updated whenever a `Ping` is received, and
+    /// stays at 0 until a reliable packet has been acknowledged.
+    #[var]
+    latency_ms: f64,
 }
 
 thread_local! {
@@ -120,6 +149,7 @@ impl INode for LunabotConn {
                 base,
                 stream_image,
                 stream_image_updated: false,
+                latency_ms: 0.0,
             };
         }
         init_panic_hook();
@@ -148,6 +178,13 @@ impl INode for LunabotConn {
                 to_lunabot: VecDeque::new(),
                 bitcode_buffer: bitcode::Buffer::new(),
                 did_reconnection: false,
+                reconnection_strategy: ReconnectionStrategy::OnFirstPacket,
+                last_reconnection_at: Instant::now(),
+                reconnection_index: None,
+                recorder: None,
+                replay: VecDeque::new(),
+                replay_start: Instant::now(),
+                had_pending_reliable: false,
                 last_steering: None,
                 send_to: None,
                 stream_lendee,
@@ -156,10 +193,12 @@ impl INode for LunabotConn {
             base,
             stream_image,
             stream_image_updated: false,
+            latency_ms: 0.0,
         }
     }
 
     fn process(&mut self, _delta: f64) {
+        self.replay_due();
         if let Some(mut inner) = self.inner.as_mut() {
             let mut received = false;
 
@@ -180,6 +219,12 @@ impl INode for LunabotConn {
                     received = true;
                     match $msg {
                         FromLunabot::Ping(stage) => {
+                            if let Some(rtt) = inner.cakap_sm.get_smoothed_rtt() {
+                                let latency_ms = rtt.as_secs_f64() * 1000.0;
+                                self.latency_ms = latency_ms;
+                                self.base_mut()
+                                    .emit_signal("latency_updated", &[latency_ms.to_variant()]);
+                            }
                             match stage {
                                 LunabotStage::TeleOp => {
                                     self.base_mut().emit_signal("entered_manual", &[])
@@ -251,7 +296,18 @@ impl INode for LunabotConn {
                                 }
                             }
                         }
-                        RecommendedAction::WaitForData | RecommendedAction::WaitForDuration(_) => {}
+                        RecommendedAction::ReliableExpired(_) => {
+                            godot_warn!("Reliable packet to lunabot expired");
+                        }
+                        RecommendedAction::PeerTimedOut => {
+                            if inner.did_reconnection {
+                                godot_warn!("Lunabot timed out");
+                                inner.did_reconnection = false;
+                            }
+                        }
+                        RecommendedAction::WaitForData
+                        | RecommendedAction::WaitForDuration(_)
+                        | RecommendedAction::DrainedReliable(_) => {}
                     }
                 };
             }
@@ -270,8 +326,7 @@ impl INode for LunabotConn {
                         // godot_warn!("{:?}", &buf[..n]);
                         inner.send_to = Some(addr);
                         if !inner.did_reconnection {
-                            let tmp_action = inner.cakap_sm.send_reconnection_msg(now).0;
-                            handle!(tmp_action);
+                            inner.reconnect(now);
                             inner.did_reconnection = true;
                         }
                         let action = inner.cakap_sm.poll(Event::IncomingData(&buf[..n]), now);
@@ -284,8 +339,15 @@ impl INode for LunabotConn {
                 }
             }
 
-            loop {
-                let action = inner.cakap_sm.poll(Event::NoEvent, now);
+            if let ReconnectionStrategy::Periodic(period) = inner.reconnection_strategy {
+                if inner.send_to.is_some()
+                    && now.duration_since(inner.last_reconnection_at) >= period
+                {
+                    inner.reconnect(now);
+                }
+            }
+
+            for action in inner.cakap_sm.drain(now) {
                 match action {
                     RecommendedAction::HandleError(cakap_error) => godot_error!("{cakap_error}"),
                     RecommendedAction::SendData(hot_packet) => {
@@ -295,25 +357,90 @@ impl INode for LunabotConn {
                             }
                         }
                     }
-                    RecommendedAction::WaitForData | RecommendedAction::WaitForDuration(_) => break,
+                    RecommendedAction::ReliableExpired(_) => {
+                        godot_warn!("Reliable packet to lunabot expired");
+                    }
+                    RecommendedAction::PeerTimedOut => {
+                        if inner.did_reconnection {
+                            godot_warn!("Lunabot timed out");
+                            inner.did_reconnection = false;
+                        }
+                    }
                     _ => unreachable!(),
                 }
             }
 
+            let has_pending_reliable = inner.cakap_sm.pending_reliable_count() > 0;
+            let all_reliable_acknowledged = inner.had_pending_reliable && !has_pending_reliable;
+            inner.had_pending_reliable = has_pending_reliable;
+
             if received {
                 self.base_mut().emit_signal("something_received", &[]);
             }
+            if all_reliable_acknowledged {
+                self.base_mut()
+                    .emit_signal("all_reliable_acknowledged", &[]);
+            }
+        }
+    }
+}
+
+impl LunabotConnInner {
+    /// Asks lunabot to clear its set of received reliable packets.
+    fn reconnect(&mut self, now: Instant) {
+        self.last_reconnection_at = now;
+        // Skip sending if the previous reconnection message is still being retransmitted
+        if self
+            .reconnection_index
+            .is_some_and(|index| self.cakap_sm.is_packet_retransmitting(index))
+        {
+            return;
+        }
+        let (action, index) = self.cakap_sm.send_reconnection_msg(now);
+        if let RecommendedAction::SendData(hot_packet) = action {
+            if let Some(addr) = self.send_to {
+                if let Err(e) = self.udp.send_to(&hot_packet, addr) {
+                    godot_error!("Failed to send hot packet: {e}");
+                }
+            }
+        }
+        self.reconnection_index = Some(index);
+    }
+
+    fn record(&mut self, action_type: u8, payload: &[u8]) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(action_type, payload) {
+                godot_error!("Failed to record command, stopping recording: {e}");
+                self.recorder = None;
+            }
         }
     }
 }
 
 impl LunabotConn {
+    fn set_reconnection_strategy(&mut self, strategy: ReconnectionStrategy) {
+        if let Some(inner) = &mut self.inner {
+            match strategy {
+                ReconnectionStrategy::OnPeerTimeout(timeout) => {
+                    inner.cakap_sm.set_peer_timeout(timeout)
+                }
+                _ => inner.cakap_sm.clear_peer_timeout(),
+            }
+            inner.reconnection_strategy = strategy;
+        }
+    }
+
     fn send_reliable(&mut self, msg: &FromLunabase) {
+        self.send_reliable_bytes(encode(msg));
+    }
+
+    fn send_reliable_bytes(&mut self, bytes: Vec<u8>) {
         if let Some(inner) = &mut self.inner {
+            inner.record(record::RELIABLE, &bytes);
             match inner
                 .cakap_sm
                 .get_packet_builder()
-                .new_reliable(encode(msg).into())
+                .new_reliable(bytes.into())
             {
                 Ok(packet) => {
                     inner.to_lunabot.push_back(Action::SendReliable(packet));
@@ -334,11 +461,12 @@ impl LunabotConn {
                     return;
                 }
             }
-            let msg = FromLunabase::Steering(new_steering);
+            let bytes = encode(&FromLunabase::Steering(new_steering));
+            inner.record(record::STEERING, &bytes);
             match inner
                 .cakap_sm
                 .get_packet_builder()
-                .new_reliable(encode(&msg).into())
+                .new_reliable(bytes.into())
             {
                 Ok(packet) => {
                     if let Some(old_idx) = last_steering_reliable_idx {
@@ -354,6 +482,31 @@ impl LunabotConn {
         }
     }
 
+    /// Sends the replayed commands whose time has come.
+    fn replay_due(&mut self) {
+        let Some(inner) = &mut self.inner else {
+            return;
+        };
+        let now = Instant::now();
+        let mut due = vec![];
+        while let Some(record) = inner.replay.front() {
+            if inner.replay_start + record.timestamp > now {
+                break;
+            }
+            due.extend(inner.replay.pop_front());
+        }
+        for record in due {
+            match record.action_type {
+                record::RELIABLE => self.send_reliable_bytes(record.payload),
+                record::STEERING => match decode(&record.payload) {
+                    Ok(FromLunabase::Steering(steering)) => self.set_steering(steering),
+                    _ => godot_error!("Invalid steering record"),
+                },
+                action_type => godot_error!("Unknown record action type: {action_type}"),
+            }
+        }
+    }
+
     // fn send_unreliable(&mut self, msg: &FromLunabase) {
     //     if let Some(inner) = &mut self.inner {
     //         match inner.cakap_sm.get_packet_builder().new_unreliable(encode(msg).into()) {
@@ -382,6 +535,12 @@ impl LunabotConn {
     fn entered_dig(&self);
     #[signal]
     fn entered_dump(&self);
+    #[signal]
+    fn latency_updated(&self, ms: f64);
+    #[signal]
+    fn peer_address_changed(&self, ip: GString);
+    #[signal]
+    fn all_reliable_acknowledged(&self);
 
     #[func]
     fn is_stream_corrupted(&self) -> bool {
@@ -400,6 +559,86 @@ impl LunabotConn {
         self.set_steering(Steering::new_left_right(left, right));
     }
 
+    #[func]
+    fn reconnect_on_first_packet(&mut self) {
+        self.set_reconnection_strategy(ReconnectionStrategy::OnFirstPacket);
+    }
+
+    #[func]
+    fn reconnect_periodically(&mut self, period_secs: f64) {
+        self.set_reconnection_strategy(ReconnectionStrategy::Periodic(Duration::from_secs_f64(
+            period_secs,
+        )));
+    }
+
+    #[func]
+    fn reconnect_on_peer_timeout(&mut self, timeout_secs: f64) {
+        self.set_reconnection_strategy(ReconnectionStrategy::OnPeerTimeout(
+            Duration::from_secs_f64(timeout_secs),
+        ));
+    }
+
+    /// Sends to the given address instead of the last address lunabot was heard from, which
+    /// may be just an IP address if the port is the same.
+    ///
+    /// Packets from the previous address will switch it back, so it should be stopped first.
+    #[func]
+    fn set_peer_address(&mut self, ip: GString) {
+        let Some(inner) = &mut self.inner else {
+            return;
+        };
+        let ip_string = ip.to_string();
+        let addr = match ip_string.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => match (ip_string.parse::<IpAddr>(), inner.send_to) {
+                (Ok(ip_addr), Some(send_to)) => SocketAddr::new(ip_addr, send_to.port()),
+                (Ok(_), None) => {
+                    godot_error!("No port is known for {ip}, so a socket address must be given");
+                    return;
+                }
+                (Err(e), _) => {
+                    godot_error!("Failed to parse peer address {ip}: {e}");
+                    return;
+                }
+            },
+        };
+        inner.send_to = Some(addr);
+        inner.reconnect(Instant::now());
+        inner.did_reconnection = true;
+        self.base_mut()
+            .emit_signal("peer_address_changed", &[ip.to_variant()]);
+    }
+
+    #[func]
+    fn start_recording(&mut self, path: GString) {
+        if let Some(inner) = &mut self.inner {
+            match Recorder::create(path.to_string()) {
+                Ok(recorder) => inner.recorder = Some(recorder),
+                Err(e) => godot_error!("Failed to start recording to {path}: {e}"),
+            }
+        }
+    }
+
+    #[func]
+    fn stop_recording(&mut self) {
+        if let Some(inner) = &mut self.inner {
+            inner.recorder = None;
+        }
+    }
+
+    #[func]
+    fn replay_recording(&mut self, path: GString) {
+        if let Some(inner) = &mut self.inner {
+            match record::read_recording(path.to_string()) {
+                Ok(records) => {
+                    inner.replay = records.into();
+                    inner.replay_start = Instant::now();
+                }
+                Err(e) => godot_error!("Failed to read recording from {path}: {e}"),
+            }
+        }
+    }
+
     #[func]
     fn continue_mission(&mut self) {
         self.send_reliable(&FromLunabase::ContinueMission);
@@ -414,4 +653,34 @@ impl LunabotConn {
     fn soft_stop(&mut self) {
         self.send_reliable(&FromLunabase::SoftStop);
     }
+
+    /// Sends a soft stop ahead of every queued command, cancelling all pending reliable packets.
+    ///
+    /// The soft stop is sent reliably to ensure it is eventually delivered, and also sent
+    /// unreliably several times so that it arrives quickly over a lossy link.
+    #[func]
+    fn emergency_stop(&mut self) {
+        let Some(inner) = &mut self.inner else {
+            return;
+        };
+        let bytes = encode(&FromLunabase::SoftStop);
+        inner.record(record::RELIABLE, &bytes);
+        let builder = inner.cakap_sm.get_packet_builder();
+        let mut actions = vec![Action::CancelAllReliable];
+        match builder.new_reliable(bytes.clone().into()) {
+            Ok(packet) => actions.push(Action::SendReliable(packet)),
+            Err(e) => godot_error!("Failed to build reliable packet: {e}"),
+        }
+        for _ in 0..EMERGENCY_STOP_UNRELIABLE_COUNT {
+            match builder.new_unreliable(bytes.clone().into()) {
+                Ok(packet) => actions.push(Action::SendUnreliable(packet)),
+                Err(e) => godot_error!("Failed to build unreliable packet: {e}"),
+            }
+        }
+        for action in actions.into_iter().rev() {
+            inner.to_lunabot.push_front(action);
+        }
+        // The last steering packet was cancelled, so the same steering must be sendable again
+        inner.last_steering = None;
+    }
 }
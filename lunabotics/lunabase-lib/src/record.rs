@@ -0,0 +1,145 @@
+//! A simple binary format for recording the commands sent to lunabot.
+//!
+//! A recording is a stream of records, each of which is:
+//!
+//! 1. The length of the rest of the record as a little endian `u32`
+//! 2. The milliseconds since the recording started as a little endian `u64`
+//! 3. The action type as a `u8`
+//! 4. The payload, which is the encoded `FromLunabase` message
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// A message that was sent reliably.
+pub const RELIABLE: u8 = 0;
+/// A steering message, which replaces the previous steering message.
+pub const STEERING: u8 = 1;
+
+/// The size of the timestamp and action type of a record.
+const HEADER_SIZE: usize = 9;
+/// The largest record length that will be read. Commands to lunabot are far smaller than this,
+/// so anything larger means the recording is corrupt.
+const MAX_RECORD_SIZE: usize = 1024 * 1024;
+
+pub struct Record {
+    pub timestamp: Duration,
+    pub action_type: u8,
+    pub payload: Vec<u8>,
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, action_type: u8, payload: &[u8]) -> std::io::Result<()> {
+        let timestamp = self.start.elapsed();
+        write_record(&mut self.writer, timestamp, action_type, payload)?;
+        // Flush every record so that nothing is lost if lunabase crashes mid-mission
+        self.writer.flush()
+    }
+}
+
+fn write_record(
+    mut writer: impl Write,
+    timestamp: Duration,
+    action_type: u8,
+    payload: &[u8],
+) -> std::io::Result<()> {
+    let len = HEADER_SIZE + payload.len();
+    if len > MAX_RECORD_SIZE {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            "Payload too large",
+        ));
+    }
+    writer.write_all(&(len as u32).to_le_bytes())?;
+    writer.write_all(&(timestamp.as_millis() as u64).to_le_bytes())?;
+    writer.write_all(&[action_type])?;
+    writer.write_all(payload)
+}
+
+pub fn read_recording(path: impl AsRef<Path>) -> std::io::Result<Vec<Record>> {
+    read_records(BufReader::new(File::open(path)?))
+}
+
+fn read_records(mut reader: impl Read) -> std::io::Result<Vec<Record>> {
+    let mut records = vec![];
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break Ok(records),
+            Err(e) => break Err(e),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if !(HEADER_SIZE..=MAX_RECORD_SIZE).contains(&len) {
+            break Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("Record of length {len} is invalid"),
+            ));
+        }
+        let mut data = Vec::with_capacity(len);
+        (&mut reader).take(len as u64).read_to_end(&mut data)?;
+        if data.len() < len {
+            break Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Record of length {len} is truncated"),
+            ));
+        }
+        let payload = data.split_off(HEADER_SIZE);
+        records.push(Record {
+            timestamp: Duration::from_millis(u64::from_le_bytes(data[0..8].try_into().unwrap())),
+            action_type: data[8],
+            payload,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut bytes = vec![];
+        write_record(&mut bytes, Duration::from_millis(5), RELIABLE, &[1, 2, 3]).unwrap();
+        write_record(&mut bytes, Duration::from_millis(1200), STEERING, &[]).unwrap();
+
+        let records = read_records(bytes.as_slice()).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].timestamp, Duration::from_millis(5));
+        assert_eq!(records[0].action_type, RELIABLE);
+        assert_eq!(records[0].payload, [1, 2, 3]);
+        assert_eq!(records[1].timestamp, Duration::from_millis(1200));
+        assert_eq!(records[1].action_type, STEERING);
+        assert!(records[1].payload.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        let too_large = vec![0u8; MAX_RECORD_SIZE];
+        assert!(write_record(vec![], Duration::ZERO, RELIABLE, &too_large).is_err());
+
+        let huge = u32::MAX.to_le_bytes();
+        let error = read_records(huge.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let mut truncated = vec![];
+        write_record(&mut truncated, Duration::ZERO, RELIABLE, &[1, 2, 3]).unwrap();
+        truncated.pop();
+        let error = read_records(truncated.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
@@ -79,6 +79,7 @@ pub fn spawn_thalassic_pipeline(
             ),
             cell_size: 0.03125,
             max_point_count: NonZeroU32::new(max_point_count).unwrap(),
+            temporal_filter_alpha: None,
         }
         .build();
 
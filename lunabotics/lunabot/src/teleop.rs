@@ -67,7 +67,7 @@ impl<F: FnMut(&[u8]) -> bool + Send + 'static> LunabaseConn<F> {
             };
 
             let mut action: RecommendedAction<'_, '_> = cakap_sm.send_reconnection_msg(Instant::now()).0;
-            let mut wait_for: Option<Duration>;
+            let mut wait_until: Option<Instant>;
 
             macro_rules! send {
                 ($data: expr) => {{
@@ -93,11 +93,11 @@ impl<F: FnMut(&[u8]) -> bool + Send + 'static> LunabaseConn<F> {
                     loop {
                         match action {
                             RecommendedAction::WaitForData => {
-                                wait_for = None;
+                                wait_until = None;
                                 break;
                             }
-                            RecommendedAction::WaitForDuration(duration) => {
-                                wait_for = Some(duration);
+                            RecommendedAction::WaitForDuration(deadline) => {
+                                wait_until = Some(deadline);
                                 break;
                             }
                             RecommendedAction::HandleError(cakap_error) => {
@@ -114,6 +114,13 @@ impl<F: FnMut(&[u8]) -> bool + Send + 'static> LunabaseConn<F> {
                             RecommendedAction::SendData(hot_packet) => {
                                 send!(&hot_packet);
                             }
+                            RecommendedAction::ReliableExpired(_) => {
+                                warn!("Reliable packet to lunabase expired");
+                                action = cakap_sm.poll(Event::NoEvent, Instant::now());
+                            }
+                            RecommendedAction::DrainedReliable(_) | RecommendedAction::PeerTimedOut => {
+                                action = cakap_sm.poll(Event::NoEvent, Instant::now());
+                            }
                         }
                     }
                 }
@@ -133,8 +140,8 @@ impl<F: FnMut(&[u8]) -> bool + Send + 'static> LunabaseConn<F> {
                         continue;
                     }
                     _ = async {
-                        if let Some(duration) = wait_for {
-                            tokio::time::sleep(duration).await;
+                        if let Some(deadline) = wait_until {
+                            tokio::time::sleep_until(deadline.into()).await;
                         } else {
                             std::future::pending::<()>().await;
                         }
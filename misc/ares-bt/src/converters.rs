@@ -1,4 +1,8 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use crate::{
     Behavior, CancelSafe, EternalBehavior, EternalStatus, FallibleBehavior, FallibleStatus,
@@ -239,6 +243,184 @@ where
     }
 }
 
+/// Fails if the inner behavior is still running after `deadline` has elapsed.
+///
+/// The timer starts the first time this behavior is run, and restarts after it
+/// returns a terminal status or is reset. The inner behavior is reset when the
+/// deadline is exceeded.
+pub struct Timeout<A> {
+    pub behavior: A,
+    pub deadline: Duration,
+    start: Option<Instant>,
+}
+
+impl<A> Timeout<A> {
+    pub fn new(deadline: Duration, behavior: A) -> Self {
+        Self {
+            behavior,
+            deadline,
+            start: None,
+        }
+    }
+}
+
+impl<A, B> Behavior<B> for Timeout<A>
+where
+    A: Behavior<B> + CancelSafe,
+{
+    fn run(&mut self, blackboard: &mut B) -> Status {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        if start.elapsed() > self.deadline {
+            self.reset();
+            return Status::Failure;
+        }
+        let status = self.behavior.run(blackboard);
+        if !status.is_running() {
+            self.start = None;
+        }
+        status
+    }
+}
+
+impl<A, B> FallibleBehavior<B> for Timeout<A>
+where
+    A: FallibleBehavior<B> + CancelSafe,
+{
+    fn run_fallible(&mut self, blackboard: &mut B) -> FallibleStatus {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        if start.elapsed() > self.deadline {
+            self.reset();
+            return FallibleStatus::Failure;
+        }
+        let status = self.behavior.run_fallible(blackboard);
+        if !status.is_running() {
+            self.start = None;
+        }
+        status
+    }
+}
+
+impl<A> CancelSafe for Timeout<A>
+where
+    A: CancelSafe,
+{
+    fn reset(&mut self) {
+        self.start = None;
+        self.behavior.reset();
+    }
+}
+
+impl<A> IntoRon for Timeout<A>
+where
+    A: IntoRon,
+{
+    fn into_ron(&self) -> ron::Value {
+        ron::Value::Map(
+            [(
+                ron::Value::String(format!("timeout({:?})", self.deadline)),
+                self.behavior.into_ron(),
+            )]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
+
+/// Fails if the inner behavior is still running after being run `max_ticks`
+/// times.
+///
+/// Unlike [`Timeout`], this does not depend on the wall clock, which makes it
+/// suitable for simulations. The tick count restarts after this behavior
+/// returns a terminal status or is reset.
+pub struct TickLimit<A> {
+    pub behavior: A,
+    pub max_ticks: usize,
+    tick_count: usize,
+}
+
+impl<A> TickLimit<A> {
+    pub fn new(max_ticks: usize, behavior: A) -> Self {
+        assert!(max_ticks > 0, "max_ticks must be at least 1");
+        Self {
+            behavior,
+            max_ticks,
+            tick_count: 0,
+        }
+    }
+}
+
+impl<A, B> Behavior<B> for TickLimit<A>
+where
+    A: Behavior<B> + CancelSafe,
+{
+    fn run(&mut self, blackboard: &mut B) -> Status {
+        self.tick_count += 1;
+        match self.behavior.run(blackboard) {
+            Status::Running => {
+                if self.tick_count >= self.max_ticks {
+                    self.reset();
+                    Status::Failure
+                } else {
+                    Status::Running
+                }
+            }
+            status => {
+                self.tick_count = 0;
+                status
+            }
+        }
+    }
+}
+
+impl<A, B> FallibleBehavior<B> for TickLimit<A>
+where
+    A: FallibleBehavior<B> + CancelSafe,
+{
+    fn run_fallible(&mut self, blackboard: &mut B) -> FallibleStatus {
+        self.tick_count += 1;
+        match self.behavior.run_fallible(blackboard) {
+            FallibleStatus::Running => {
+                if self.tick_count >= self.max_ticks {
+                    self.reset();
+                    FallibleStatus::Failure
+                } else {
+                    FallibleStatus::Running
+                }
+            }
+            FallibleStatus::Failure => {
+                self.tick_count = 0;
+                FallibleStatus::Failure
+            }
+        }
+    }
+}
+
+impl<A> CancelSafe for TickLimit<A>
+where
+    A: CancelSafe,
+{
+    fn reset(&mut self) {
+        self.tick_count = 0;
+        self.behavior.reset();
+    }
+}
+
+impl<A> IntoRon for TickLimit<A>
+where
+    A: IntoRon,
+{
+    fn into_ron(&self) -> ron::Value {
+        ron::Value::Map(
+            [(
+                ron::Value::String(format!("tick_limit({})", self.max_ticks)),
+                self.behavior.into_ron(),
+            )]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
+
 pub struct Rename<A> {
     pub name: Cow<'static, str>,
     pub behavior: A,
@@ -284,3 +466,84 @@ where
         self.call_mut(args)
     }
 }
+
+/// A behavior that is shared between trees.
+///
+/// The inner behavior is locked every time this behavior is run, so a subtree
+/// can be defined once and included in several trees. To run a subtree that
+/// operates on a smaller blackboard, wrap it in a closure that borrows the
+/// relevant part of the outer blackboard.
+pub struct Subtree<A>(pub Arc<Mutex<A>>);
+
+impl<A> Subtree<A> {
+    pub fn new(behavior: A) -> Self {
+        Self(Arc::new(Mutex::new(behavior)))
+    }
+}
+
+impl<A> Clone for Subtree<A> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<A, B> Behavior<B> for Subtree<A>
+where
+    A: Behavior<B>,
+{
+    fn run(&mut self, blackboard: &mut B) -> Status {
+        self.0.lock().unwrap().run(blackboard)
+    }
+}
+
+impl<A, B> InfallibleBehavior<B> for Subtree<A>
+where
+    A: InfallibleBehavior<B>,
+{
+    fn run_infallible(&mut self, blackboard: &mut B) -> InfallibleStatus {
+        self.0.lock().unwrap().run_infallible(blackboard)
+    }
+}
+
+impl<A, B> FallibleBehavior<B> for Subtree<A>
+where
+    A: FallibleBehavior<B>,
+{
+    fn run_fallible(&mut self, blackboard: &mut B) -> FallibleStatus {
+        self.0.lock().unwrap().run_fallible(blackboard)
+    }
+}
+
+impl<A, B> EternalBehavior<B> for Subtree<A>
+where
+    A: EternalBehavior<B>,
+{
+    fn run_eternal(&mut self, blackboard: &mut B) -> EternalStatus {
+        self.0.lock().unwrap().run_eternal(blackboard)
+    }
+}
+
+impl<A> CancelSafe for Subtree<A>
+where
+    A: CancelSafe,
+{
+    fn reset(&mut self) {
+        self.0.lock().unwrap().reset();
+    }
+}
+
+impl<A> IntoRon for Subtree<A>
+where
+    A: IntoRon,
+{
+    fn into_ron(&self) -> ron::Value {
+        ron::Value::Map(
+            [(
+                ron::Value::String("subtree".to_string()),
+                self.0.lock().unwrap().into_ron(),
+            )]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
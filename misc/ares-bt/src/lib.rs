@@ -188,7 +188,12 @@ impl From<EternalStatus> for Status {
 
 #[cfg(test)]
 mod tests {
-    use looping::WhileLoop;
+    use std::time::Duration;
+
+    use action::AlwaysRunning;
+    use converters::{AssertCancelSafe, Subtree, TickLimit, Timeout};
+    use looping::{Retry, WhileLoop};
+    use sequence::{Parallel, Sequence};
 
     use super::*;
 
@@ -207,4 +212,102 @@ mod tests {
         assert!(is_ok);
         assert_eq!(sum, 10);
     }
+
+    #[test]
+    fn test_parallel() {
+        let mut ticks = [0usize; 3];
+        let mut parallel = Parallel::new(
+            (
+                AssertCancelSafe(|ticks: &mut [usize; 3]| {
+                    ticks[0] += 1;
+                    Status::Success
+                }),
+                AssertCancelSafe(|ticks: &mut [usize; 3]| {
+                    ticks[1] += 1;
+                    if ticks[1] < 3 {
+                        Status::Running
+                    } else {
+                        Status::Success
+                    }
+                }),
+                AssertCancelSafe(|ticks: &mut [usize; 3]| {
+                    ticks[2] += 1;
+                    Status::Failure
+                }),
+            ),
+            2,
+            2,
+        );
+        assert_eq!(parallel.run(&mut ticks), Status::Running);
+        assert_eq!(parallel.run(&mut ticks), Status::Running);
+        assert_eq!(parallel.run(&mut ticks), Status::Success);
+        assert_eq!(ticks, [1, 3, 1]);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut timeout = Timeout::new(Duration::from_millis(10), AlwaysRunning);
+        assert_eq!(timeout.run(&mut ()), Status::Running);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(timeout.run(&mut ()), Status::Failure);
+        // The timer restarts after the timeout fires.
+        assert_eq!(timeout.run(&mut ()), Status::Running);
+    }
+
+    #[test]
+    fn test_retry() {
+        let mut attempts = 0usize;
+        let mut retry = Retry::new(3, |attempts: &mut usize| {
+            *attempts += 1;
+            (*attempts % 4 == 0).into()
+        });
+        // Fails on every attempt.
+        assert_eq!(retry.run(&mut attempts), Status::Failure);
+        assert_eq!(attempts, 3);
+        // The attempt counter was restarted, so the 4th attempt gets to run.
+        assert_eq!(retry.run(&mut attempts), Status::Success);
+        assert_eq!(attempts, 4);
+    }
+
+    #[test]
+    fn test_tick_limit() {
+        let mut ticks = 0usize;
+        let mut tick_limit = TickLimit::new(
+            3,
+            AssertCancelSafe(|ticks: &mut usize| {
+                *ticks += 1;
+                Status::Running
+            }),
+        );
+        assert_eq!(tick_limit.run(&mut ticks), Status::Running);
+        assert_eq!(tick_limit.run(&mut ticks), Status::Running);
+        assert_eq!(tick_limit.run(&mut ticks), Status::Failure);
+        assert_eq!(ticks, 3);
+        // The tick count restarts after the limit fires.
+        assert_eq!(tick_limit.run(&mut ticks), Status::Running);
+        assert_eq!(tick_limit.run(&mut ticks), Status::Running);
+        assert_eq!(tick_limit.run(&mut ticks), Status::Failure);
+        assert_eq!(ticks, 6);
+    }
+
+    #[test]
+    fn test_subtree() {
+        let arm = Subtree::new(|arm: &mut usize| {
+            *arm += 1;
+            Status::Success
+        });
+        let mut blackboard = (0usize, 0usize);
+        let mut tree = Sequence::new((
+            {
+                let mut arm = arm.clone();
+                move |blackboard: &mut (usize, usize)| arm.run(&mut blackboard.1)
+            },
+            {
+                let mut arm = arm.clone();
+                move |blackboard: &mut (usize, usize)| arm.run(&mut blackboard.1)
+            },
+        ));
+        assert_eq!(tree.run(&mut blackboard), Status::Success);
+        assert_eq!(blackboard, (0, 2));
+    }
 }
@@ -143,3 +143,76 @@ impl<A, B> WhileLoop<A, B> {
         }
     }
 }
+
+/// Runs the inner behavior again each time it fails, up to `max_attempts` times
+/// in total.
+///
+/// Succeeds as soon as the inner behavior succeeds, and fails once every
+/// attempt has failed. The attempt counter restarts after this behavior returns
+/// a terminal status or is reset.
+pub struct Retry<A> {
+    pub body: A,
+    pub max_attempts: usize,
+    remaining: usize,
+}
+
+impl<A, D> Behavior<D> for Retry<A>
+where
+    A: Behavior<D>,
+{
+    fn run(&mut self, blackboard: &mut D) -> Status {
+        loop {
+            match self.body.run(blackboard) {
+                Status::Running => return Status::Running,
+                Status::Success => {
+                    self.remaining = self.max_attempts;
+                    return Status::Success;
+                }
+                Status::Failure => {
+                    self.remaining -= 1;
+                    if self.remaining == 0 {
+                        self.remaining = self.max_attempts;
+                        return Status::Failure;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<A> CancelSafe for Retry<A>
+where
+    A: CancelSafe,
+{
+    fn reset(&mut self) {
+        self.remaining = self.max_attempts;
+        self.body.reset();
+    }
+}
+
+impl<A> IntoRon for Retry<A>
+where
+    A: IntoRon,
+{
+    fn into_ron(&self) -> ron::Value {
+        ron::Value::Map(
+            [(
+                ron::Value::String(format!("retry({})", self.max_attempts)),
+                self.body.into_ron(),
+            )]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
+
+impl<A> Retry<A> {
+    pub fn new(max_attempts: usize, body: A) -> Self {
+        assert!(max_attempts > 0, "max_attempts must be at least 1");
+        Self {
+            body,
+            max_attempts,
+            remaining: max_attempts,
+        }
+    }
+}
@@ -755,3 +755,155 @@ impl<A> ParallelAny<A> {
         Self { body, index: 0 }
     }
 }
+
+/// Runs all of its children concurrently, ticking one unfinished child per run.
+///
+/// Returns `Success` once `success_threshold` children have succeeded, and
+/// `Failure` once `failure_threshold` children have failed. Children that have
+/// already succeeded or failed are not run again until this node is reset. If
+/// every child finishes without either threshold being met, this node fails.
+///
+/// With a `success_threshold` equal to the number of children and a
+/// `failure_threshold` of 1, this behaves like [`ParallelSequence`]. With a
+/// `success_threshold` of 1 and a `failure_threshold` equal to the number of
+/// children, this behaves like [`ParallelSelect`].
+///
+/// ```
+/// use ares_bt::{
+///     action::{AlwaysFail, AlwaysRunning, AlwaysSucceed},
+///     sequence::Parallel,
+///     Behavior, Status,
+/// };
+///
+/// // Succeeds once any 2 of the 3 children succeed.
+/// let mut parallel = Parallel::new((AlwaysSucceed, AlwaysFail, AlwaysSucceed), 2, 2);
+/// assert_eq!(parallel.run(&mut ()), Status::Success);
+///
+/// // Keeps running while the second child has yet to finish.
+/// let mut parallel = Parallel::new((AlwaysSucceed, AlwaysRunning), 2, 1);
+/// assert_eq!(parallel.run(&mut ()), Status::Running);
+/// ```
+pub struct Parallel<A> {
+    pub body: A,
+    pub success_threshold: usize,
+    pub failure_threshold: usize,
+    index: usize,
+    finished: u8,
+    succeeded: usize,
+    failed: usize,
+}
+
+macro_rules! impl_par {
+    ($len: literal $($name: ident $num: tt)+) => {
+        impl<C1, $($name,)+> Behavior<C1> for Parallel<($($name,)+)>
+        where
+            $($name: Behavior<C1>,)+
+            Self: CancelSafe
+        {
+            fn run(&mut self, blackboard: &mut C1) -> Status {
+                loop {
+                    if self.finished.count_ones() as usize == $len {
+                        self.reset();
+                        return Status::Failure;
+                    }
+                    let index = self.index;
+                    self.index += 1;
+                    if self.index == $len {
+                        self.index = 0;
+                    }
+                    if self.finished & (1 << index) != 0 {
+                        continue;
+                    }
+                    let status = match index {
+                        $(
+                            $num => self.body.$num.run(blackboard),
+                        )+
+                        _ => unreachable!(),
+                    };
+                    match status {
+                        Status::Running => return Status::Running,
+                        Status::Success => {
+                            self.finished |= 1 << index;
+                            self.succeeded += 1;
+                            if self.succeeded >= self.success_threshold {
+                                self.reset();
+                                return Status::Success;
+                            }
+                        }
+                        Status::Failure => {
+                            self.finished |= 1 << index;
+                            self.failed += 1;
+                            if self.failed >= self.failure_threshold {
+                                self.reset();
+                                return Status::Failure;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        impl<$($name,)+> CancelSafe for Parallel<($($name,)+)>
+        where
+            $($name: CancelSafe,)+
+        {
+            fn reset(&mut self) {
+                self.index = 0;
+                self.finished = 0;
+                self.succeeded = 0;
+                self.failed = 0;
+                $(
+                    self.body.$num.reset();
+                )+
+            }
+        }
+
+        impl<$($name,)+> IntoRon for Parallel<($($name,)+)>
+        where
+            $($name: IntoRon,)+
+        {
+            fn into_ron(&self) -> ron::Value {
+                ron::Value::Map(
+                    [
+                        (ron::Value::String("parallel".to_string()), ron::Value::Seq(
+                            vec![
+                                $(
+                                    self.body.$num.into_ron(),
+                                )+
+                            ].into_iter().collect()
+                        ))
+                    ].into_iter().collect()
+                )
+            }
+        }
+    }
+}
+
+impl_par!(1 A 0);
+impl_par!(2 A 0 B 1);
+impl_par!(3 A 0 B 1 C 2);
+impl_par!(4 A 0 B 1 C 2 D 3);
+impl_par!(5 A 0 B 1 C 2 D 3 E 4);
+impl_par!(6 A 0 B 1 C 2 D 3 E 4 F 5);
+impl_par!(7 A 0 B 1 C 2 D 3 E 4 F 5 G 6);
+
+impl<A> Parallel<A> {
+    pub fn new(body: A, success_threshold: usize, failure_threshold: usize) -> Self {
+        assert!(
+            success_threshold > 0,
+            "success_threshold must be at least 1"
+        );
+        assert!(
+            failure_threshold > 0,
+            "failure_threshold must be at least 1"
+        );
+        Self {
+            body,
+            success_threshold,
+            failure_threshold,
+            index: 0,
+            finished: 0,
+            succeeded: 0,
+            failed: 0,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+#![no_main]
+
+use std::time::{Duration, Instant};
+
+use cakap2::{Event, PeerStateMachine, RecommendedAction};
+use libfuzzer_sys::fuzz_target;
+
+const MAX_RECEIVED_SET_SIZE: usize = 16;
+
+// The input is split into packets, each prefixed with a single length byte.
+fuzz_target!(|data: &[u8]| {
+    let mut state_machine =
+        PeerStateMachine::new(Duration::from_millis(100), MAX_RECEIVED_SET_SIZE, 1400);
+    let builder = state_machine.get_packet_builder();
+    let mut now = Instant::now();
+    let mut data = data;
+    let mut sent = 0usize;
+    let mut expired = 0usize;
+
+    while let Some((&len, rest)) = data.split_first() {
+        let len = (len as usize).min(rest.len());
+        let (packet, rest) = rest.split_at(len);
+        data = rest;
+
+        // Keep some reliable packets in flight so that acknowledgements can match them
+        if let Ok(reliable) = builder.new_reliable(packet.to_vec().into()) {
+            state_machine.poll(Event::Action(reliable.into()), now);
+            sent += 1;
+        }
+        let pending_before = state_machine.pending_reliable_count();
+        let expired_before = expired;
+
+        // The state machine must always settle into waiting
+        let mut action = state_machine.poll(Event::IncomingData(packet), now);
+        loop {
+            match action {
+                RecommendedAction::WaitForData | RecommendedAction::WaitForDuration(_) => break,
+                RecommendedAction::ReliableExpired(_) => expired += 1,
+                _ => {}
+            }
+            action = state_machine.poll(Event::NoEvent, now);
+        }
+
+        let stats = state_machine.stats();
+        assert!(stats.received_set_size <= MAX_RECEIVED_SET_SIZE);
+        // Incoming data can only acknowledge or expire pending packets, never add them
+        assert!(stats.pending_reliable_count + (expired - expired_before) <= pending_before);
+        // Every pending packet was sent and has neither expired nor been acknowledged
+        assert!(stats.pending_reliable_count + expired <= sent);
+        now += Duration::from_millis(len as u64);
+    }
+});
@@ -0,0 +1,348 @@
+//! The collections used by the state machine, which are swapped out for fixed capacity ones
+//! when the `std` feature is disabled.
+
+use core::num::NonZeroU64;
+
+/// The maximum number of reliable packets that can be pending at once without `std`.
+pub const MAX_PENDING_RELIABLE: usize = 64;
+/// The maximum number of reliable indices from the peer that are remembered without `std`.
+///
+/// `max_received_set_size` is capped to this value.
+pub const MAX_RECEIVED_SET_SIZE: usize = 1024;
+
+#[cfg(feature = "std")]
+pub(crate) type ReliableMap<V> = HashReliableMap<V>;
+#[cfg(not(feature = "std"))]
+pub(crate) type ReliableMap<V> = SlotMap<V, MAX_PENDING_RELIABLE>;
+
+#[cfg(feature = "std")]
+pub(crate) type ReceivedSet = IndexReceivedSet;
+#[cfg(not(feature = "std"))]
+pub(crate) type ReceivedSet = ReceivedWindow<MAX_RECEIVED_SET_SIZE>;
+
+/// A map from reliable indices to `V` that never runs out of space.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub(crate) struct HashReliableMap<V>(fxhash::FxHashMap<NonZeroU64, V>);
+
+#[cfg(feature = "std")]
+impl<V> Default for HashReliableMap<V> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<V> HashReliableMap<V> {
+    /// Inserts a value for an index that is not in the map yet.
+    pub(crate) fn try_insert(&mut self, index: NonZeroU64, value: V) -> Result<(), V> {
+        let option = self.0.insert(index, value);
+        debug_assert!(option.is_none());
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, index: &NonZeroU64) -> Option<&V> {
+        self.0.get(index)
+    }
+
+    pub(crate) fn get_mut(&mut self, index: &NonZeroU64) -> Option<&mut V> {
+        self.0.get_mut(index)
+    }
+
+    pub(crate) fn contains_key(&self, index: &NonZeroU64) -> bool {
+        self.0.contains_key(index)
+    }
+
+    pub(crate) fn remove(&mut self, index: &NonZeroU64) -> Option<V> {
+        self.0.remove(index)
+    }
+
+    pub(crate) fn retain(&mut self, f: impl FnMut(&NonZeroU64, &mut V) -> bool) {
+        self.0.retain(f);
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.0.values()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// A map from reliable indices to `V` with room for `N` entries.
+///
+/// Each index is stored in the first free slot at or after `index % N`. Since reliable indices are
+/// handed out sequentially, an index is almost always found in its first slot.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "std", allow(dead_code))]
+pub(crate) struct SlotMap<V, const N: usize> {
+    slots: [Option<(NonZeroU64, V)>; N],
+    len: usize,
+}
+
+impl<V, const N: usize> Default for SlotMap<V, N> {
+    fn default() -> Self {
+        Self {
+            slots: core::array::from_fn(|_| None),
+            len: 0,
+        }
+    }
+}
+
+#[cfg_attr(feature = "std", allow(dead_code))]
+impl<V, const N: usize> SlotMap<V, N> {
+    /// Returns the slots to look in for the given index, in order.
+    fn probe(index: NonZeroU64) -> impl Iterator<Item = usize> {
+        let start = (index.get() % N as u64) as usize;
+        (0..N).map(move |i| (start + i) % N)
+    }
+
+    fn find(&self, index: &NonZeroU64) -> Option<usize> {
+        Self::probe(*index)
+            .find(|&slot| matches!(&self.slots[slot], Some((key, _)) if key == index))
+    }
+
+    /// Inserts a value for an index that is not in the map yet, returning the value back if the
+    /// map is full.
+    pub(crate) fn try_insert(&mut self, index: NonZeroU64, value: V) -> Result<(), V> {
+        debug_assert!(!self.contains_key(&index));
+        let Some(slot) = Self::probe(index).find(|&slot| self.slots[slot].is_none()) else {
+            return Err(value);
+        };
+        self.slots[slot] = Some((index, value));
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, index: &NonZeroU64) -> Option<&V> {
+        let slot = self.find(index)?;
+        self.slots[slot].as_ref().map(|(_, value)| value)
+    }
+
+    pub(crate) fn get_mut(&mut self, index: &NonZeroU64) -> Option<&mut V> {
+        let slot = self.find(index)?;
+        self.slots[slot].as_mut().map(|(_, value)| value)
+    }
+
+    pub(crate) fn contains_key(&self, index: &NonZeroU64) -> bool {
+        self.find(index).is_some()
+    }
+
+    pub(crate) fn remove(&mut self, index: &NonZeroU64) -> Option<V> {
+        let slot = self.find(index)?;
+        self.len -= 1;
+        self.slots[slot].take().map(|(_, value)| value)
+    }
+
+    pub(crate) fn retain(&mut self, mut f: impl FnMut(&NonZeroU64, &mut V) -> bool) {
+        for slot in &mut self.slots {
+            if let Some((index, value)) = slot {
+                if !f(index, value) {
+                    *slot = None;
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.slots.iter().flatten().map(|(_, value)| value)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+        self.len = 0;
+    }
+}
+
+/// The set of reliable indices recently received from the peer, which forgets the oldest
+/// index when it grows too large.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IndexReceivedSet(indexmap::IndexSet<NonZeroU64>);
+
+#[cfg(feature = "std")]
+impl IndexReceivedSet {
+    /// Inserts the given index, returning `false` if it was already present.
+    pub(crate) fn insert(&mut self, index: NonZeroU64, max_size: usize) -> bool {
+        if !self.0.insert(index) {
+            return false;
+        }
+        if self.0.len() > max_size {
+            self.0.shift_remove_index(0);
+        }
+        true
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// The set of reliable indices received from the peer within a window below the highest index
+/// received, stored as a ring of `N` bits.
+///
+/// Unlike the set used with `std`, indices are forgotten once they fall `max_size` (capped at `N`)
+/// below the highest index, rather than once `max_size` newer indices have been received. Since
+/// the peer hands out indices sequentially, the two are the same unless packets are lost.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "std", allow(dead_code))]
+pub(crate) struct ReceivedWindow<const N: usize> {
+    /// Bit `index % N` is set if `index` was received.
+    bits: [bool; N],
+    highest: u64,
+    len: usize,
+}
+
+impl<const N: usize> Default for ReceivedWindow<N> {
+    fn default() -> Self {
+        Self {
+            bits: [false; N],
+            highest: 0,
+            len: 0,
+        }
+    }
+}
+
+#[cfg_attr(feature = "std", allow(dead_code))]
+impl<const N: usize> ReceivedWindow<N> {
+    fn unset(&mut self, index: u64) {
+        let bit = &mut self.bits[(index % N as u64) as usize];
+        if *bit {
+            *bit = false;
+            self.len -= 1;
+        }
+    }
+
+    /// Inserts the given index, returning `false` if it was already present.
+    ///
+    /// Indices that have already been forgotten are always considered new.
+    pub(crate) fn insert(&mut self, index: NonZeroU64, max_size: usize) -> bool {
+        let window = max_size.clamp(1, N) as u64;
+        let index = index.get();
+        if index > self.highest {
+            // Forget the indices that fall out of the window
+            let forget_start = self.highest.saturating_sub(window) + 1;
+            let forget_end = index.saturating_sub(window);
+            if forget_end.saturating_sub(forget_start) >= N as u64 {
+                self.clear();
+            } else {
+                for old in forget_start..=forget_end {
+                    self.unset(old);
+                }
+            }
+            self.highest = index;
+        } else if self.highest - index >= window {
+            return true;
+        }
+        let bit = &mut self.bits[(index % N as u64) as usize];
+        if *bit {
+            return false;
+        }
+        *bit = true;
+        self.len += 1;
+        true
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bits = [false; N];
+        self.highest = 0;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(index: u64) -> NonZeroU64 {
+        NonZeroU64::new(index).unwrap()
+    }
+
+    #[test]
+    fn slot_map_1() {
+        let mut map = SlotMap::<u64, 4>::default();
+        for i in [1, 2, 5, 9] {
+            assert!(map.try_insert(index(i), i * 10).is_ok());
+        }
+        assert_eq!(map.len(), 4);
+        // Full
+        assert_eq!(map.try_insert(index(3), 30), Err(30));
+        // 5 and 9 collided with 1, so they were probed into other slots
+        for i in [1, 2, 5, 9] {
+            assert_eq!(map.get(&index(i)), Some(&(i * 10)));
+        }
+        assert_eq!(map.remove(&index(5)), Some(50));
+        assert!(!map.contains_key(&index(5)));
+        assert!(map.try_insert(index(3), 30).is_ok());
+        *map.get_mut(&index(3)).unwrap() += 1;
+        assert_eq!(map.get(&index(3)), Some(&31));
+
+        map.retain(|&i, _| i.get() % 2 == 1);
+        assert_eq!(map.len(), 3);
+        assert!(!map.contains_key(&index(2)));
+        let mut values: Vec<_> = map.values().copied().collect();
+        values.sort_unstable();
+        assert_eq!(values, [10, 31, 90]);
+
+        map.clear();
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.get(&index(1)), None);
+    }
+
+    #[test]
+    fn received_window_1() {
+        let mut set = ReceivedWindow::<8>::default();
+        assert!(set.insert(index(1), 4));
+        assert!(!set.insert(index(1), 4));
+        assert!(set.insert(index(3), 4));
+        assert!(set.insert(index(2), 4));
+        assert!(!set.insert(index(3), 4));
+        assert_eq!(set.len(), 3);
+
+        // 1 falls out of the window of 4 below 5
+        assert!(set.insert(index(5), 4));
+        assert_eq!(set.len(), 3);
+        assert!(set.insert(index(1), 4));
+        assert!(!set.insert(index(2), 4));
+
+        // A jump larger than the ring forgets everything
+        assert!(set.insert(index(100), 4));
+        assert_eq!(set.len(), 1);
+        assert!(set.insert(index(2), 4));
+        assert!(!set.insert(index(100), 4));
+
+        set.clear();
+        assert_eq!(set.len(), 0);
+        assert!(set.insert(index(100), 4));
+    }
+
+    #[test]
+    fn received_window_2() {
+        // The window is capped by the size of the ring
+        let mut set = ReceivedWindow::<4>::default();
+        for i in 1..=6 {
+            assert!(set.insert(index(i), 100));
+        }
+        assert_eq!(set.len(), 4);
+        assert!(!set.insert(index(3), 100));
+        assert!(set.insert(index(2), 100));
+    }
+}
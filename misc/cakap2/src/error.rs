@@ -1,4 +1,5 @@
-use std::fmt::Display;
+use alloc::vec::Vec;
+use core::fmt::Display;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum CakapError {
@@ -8,27 +9,70 @@ pub enum CakapError {
     PacketTooLong,
     /// A packet from the peer was invalid.
     InvalidPacket,
+    /// A payload given by the caller was larger than
+    /// [`PacketBuilder::max_payload_size`](crate::packet::PacketBuilder::max_payload_size).
+    PayloadTooLarge { actual_size: usize, max_size: usize },
+    /// A reliable packet could not be sent because [`MAX_PENDING_RELIABLE`](crate::MAX_PENDING_RELIABLE)
+    /// reliable packets are already pending. This can only happen without the `std` feature.
+    TooManyPendingReliable,
+}
+
+impl CakapError {
+    /// Returns `true` if the error was caused by the peer or the transport layer, instead of by
+    /// the caller of this crate.
+    ///
+    /// Fatal errors suggest that the connection is misbehaving, while other errors can be
+    /// recovered from by the caller (such as by sending a smaller payload).
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::PacketTooSmall | Self::PacketTooLong | Self::InvalidPacket => true,
+            Self::PayloadTooLarge { .. } | Self::TooManyPendingReliable => false,
+        }
+    }
 }
 
 impl Display for CakapError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::PacketTooSmall => write!(f, "Packet from peer was too small to be processed"),
             Self::PacketTooLong => write!(f, "Packet from peer was too large to be processed"),
             Self::InvalidPacket => write!(f, "Packet from peer was invalid"),
+            Self::PayloadTooLarge {
+                actual_size,
+                max_size,
+            } => write!(
+                f,
+                "Payload too large: {actual_size} bytes, max allowed is {max_size}"
+            ),
+            Self::TooManyPendingReliable => write!(f, "Too many reliable packets are pending"),
         }
     }
 }
 
-impl std::error::Error for CakapError {}
+impl core::error::Error for CakapError {}
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug)]
 pub enum BuildPacketError {
-    #[error("Buffer too large, max packet size: {max_packet_size}")]
-    BufferTooLarge {
+    /// The payload was larger than
+    /// [`PacketBuilder::max_payload_size`](crate::packet::PacketBuilder::max_payload_size).
+    ///
+    /// `error` is always [`CakapError::PayloadTooLarge`].
+    PayloadTooLarge {
+        buffer: Vec<u8>,
+        error: CakapError,
+    },
+    EmptyBuffer {
         buffer: Vec<u8>,
-        max_packet_size: usize,
     },
-    #[error("Buffer is empty")]
-    EmptyBuffer { buffer: Vec<u8> },
 }
+
+impl Display for BuildPacketError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::PayloadTooLarge { error, .. } => error.fmt(f),
+            Self::EmptyBuffer { .. } => write!(f, "Buffer is empty"),
+        }
+    }
+}
+
+impl core::error::Error for BuildPacketError {}
@@ -0,0 +1,45 @@
+use crate::{
+    error::BuildPacketError,
+    packet::{Action, PacketBody, PacketBuilder, ReliableIndex},
+    time::Timestamp,
+    Event, PeerStateMachine, RecommendedAction,
+};
+
+/// A stream of reliable packets where only the most recent packet is guaranteed to arrive.
+///
+/// Sending a new packet cancels the retransmission of the previous one, so the peer will
+/// *eventually* receive the latest value, but may not receive every value in between. This
+/// is useful for things like steering commands, where only the latest value matters.
+///
+/// Created with [`PeerStateMachine::begin_eventual_stream`].
+#[derive(Clone, Debug)]
+pub struct EventualStream {
+    pub(crate) builder: PacketBuilder,
+    pub(crate) last_index: Option<ReliableIndex>,
+}
+
+impl EventualStream {
+    /// Sends the given data reliably through the given state machine, cancelling the previous
+    /// packet sent through this stream if it has not been acknowledged yet.
+    ///
+    /// The given state machine should be the same one that created this stream.
+    pub fn send<'a, I: Timestamp>(
+        &mut self,
+        state_machine: &'a mut PeerStateMachine<I>,
+        data: PacketBody,
+        now: I,
+    ) -> Result<RecommendedAction<'a, 'static, I>, BuildPacketError> {
+        let packet = self.builder.new_reliable(data)?;
+        if let Some(old_index) = self.last_index.replace(packet.get_index()) {
+            // Cancelling through `poll` could produce a retransmission that we would have to
+            // drop, so the packet is removed directly instead.
+            state_machine.retransmission_map.remove(&old_index.0);
+        }
+        Ok(state_machine.poll(Event::Action(Action::SendReliable(packet)), now))
+    }
+
+    /// Returns the index of the last packet sent through this stream, if any.
+    pub fn get_last_index(&self) -> Option<ReliableIndex> {
+        self.last_index
+    }
+}
@@ -9,30 +9,54 @@
 //! an event loop for each unique connection and poll the state machine with incoming events. The state machine
 //! will then produce a [`RecommendedAction`] that you should take.
 //!
+//! # `no_std`
+//! This crate only requires `alloc`. Disabling the default `std` feature swaps out the hash based collections
+//! for fixed capacity ones, and replaces [`std::time::Instant`] with any type implementing [`time::Timestamp`].
+//! At most [`MAX_PENDING_RELIABLE`] reliable packets can then be pending at once, and the received set holds at
+//! most [`MAX_RECEIVED_SET_SIZE`] indices.
+//! Targets without 64-bit atomics should also enable the `portable-atomic` feature.
+//!
 //! # Security
 //! Since this protocol is handshakeless and connectionless, it is vulnerable to abuse. This protocol is not intended
 //! to be used on the open internet in production, but rather in a controlled environment without bad actors. The Utah
 //! Student Robotics club uses this protocol to communicate between an operator and a robot in a network with no other
 //! clients.
 
-use std::{
-    collections::VecDeque,
-    num::NonZeroU64,
-    sync::{atomic::AtomicU64, Arc},
-    time::{Duration, Instant},
-    u64,
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeSet, VecDeque},
+    sync::Arc,
+    vec::Vec,
 };
+use core::{num::NonZeroU64, sync::atomic::Ordering, time::Duration};
+
+#[cfg(not(feature = "portable-atomic"))]
+use core::sync::atomic::AtomicU64;
+#[cfg(feature = "portable-atomic")]
+use portable_atomic::AtomicU64;
 
+use collections::{ReceivedSet, ReliableMap};
+pub use collections::{MAX_PENDING_RELIABLE, MAX_RECEIVED_SET_SIZE};
 use error::CakapError;
-use fxhash::FxHashMap;
-use indexmap::IndexSet;
+use eventual::EventualStream;
 use packet::{
     Action, HotPacket, HotPacketInner, PacketBuilder, ReliableIndex, ReliablePacket,
     UnreliablePacket,
 };
+use rate_limit::RateLimiter;
+use time::{DefaultTimestamp, Timestamp};
 
+mod collections;
 pub mod error;
+pub mod eventual;
 pub mod packet;
+pub mod rate_limit;
+pub mod time;
+pub mod typed;
 
 #[derive(Debug)]
 pub struct Shared {
@@ -40,55 +64,269 @@ pub struct Shared {
     max_packet_size: usize,
 }
 
-#[derive(Debug)]
-struct Retransmit {
-    send_at: Instant,
+#[derive(Debug, Clone)]
+struct Retransmit<I> {
+    sent_at: I,
+    send_at: I,
+    retry_count: u32,
     data: Box<[u8]>,
 }
 
-pub struct PeerStateMachine {
+/// A packet that has not been sent for the first time due to the rate limiter.
+#[derive(Debug, Clone)]
+enum Withheld {
+    /// The index of a reliable packet in the retransmission map.
+    Reliable(NonZeroU64),
+    Unreliable(Box<[u8]>),
+}
+
+/// Determines how long to wait before retransmitting an unacknowledged reliable packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetransmissionPolicy {
+    /// Retransmit every time the given [`Duration`] elapses, until the packet is acknowledged or cancelled.
+    Fixed(Duration),
+    /// Wait `base * multiplier^min(retry_count, max_exponent)` before each retransmission, capped at `cap`.
+    ///
+    /// If `max_retries` is `Some`, the packet is abandoned after that many retransmissions and
+    /// [`RecommendedAction::ReliableExpired`] is returned.
+    ExponentialBackoff {
+        base: Duration,
+        multiplier: f64,
+        max_exponent: u32,
+        cap: Duration,
+        max_retries: Option<u32>,
+    },
+}
+
+impl RetransmissionPolicy {
+    /// The amount of time to wait after a packet has been retransmitted `retry_count` times.
+    fn delay(&self, retry_count: u32) -> Duration {
+        match *self {
+            Self::Fixed(duration) => duration,
+            Self::ExponentialBackoff {
+                base,
+                multiplier,
+                max_exponent,
+                cap,
+                ..
+            } => {
+                // `powi` is not available without `std`
+                let factor =
+                    (0..retry_count.min(max_exponent)).fold(1.0, |acc, _| acc * multiplier);
+                Duration::try_from_secs_f64(base.as_secs_f64() * factor)
+                    .unwrap_or(cap)
+                    .min(cap)
+            }
+        }
+    }
+
+    fn is_expired(&self, retry_count: u32) -> bool {
+        match *self {
+            Self::Fixed(_) => false,
+            Self::ExponentialBackoff { max_retries, .. } => {
+                matches!(max_retries, Some(max_retries) if retry_count >= max_retries)
+            }
+        }
+    }
+}
+
+/// A builder for [`PeerStateMachine`].
+///
+/// See [`PeerStateMachine::new`] for the meaning of each option. The defaults are a fixed
+/// retransmission duration of 150ms, a maximum received set size of 1024, and a maximum
+/// packet size of 1400 bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerStateMachineBuilder {
+    retransmission_policy: RetransmissionPolicy,
+    max_received_set_size: usize,
+    max_packet_size: usize,
+    peer_timeout: Option<Duration>,
+}
+
+impl Default for PeerStateMachineBuilder {
+    fn default() -> Self {
+        Self {
+            retransmission_policy: RetransmissionPolicy::Fixed(Duration::from_millis(150)),
+            max_received_set_size: 1024,
+            max_packet_size: 1400,
+            peer_timeout: None,
+        }
+    }
+}
+
+impl PeerStateMachineBuilder {
+    /// Sets a [`RetransmissionPolicy::Fixed`] policy with the given duration.
+    pub fn retransmission_duration(self, retransmission_duration: Duration) -> Self {
+        self.retransmission_policy(RetransmissionPolicy::Fixed(retransmission_duration))
+    }
+
+    pub fn retransmission_policy(mut self, retransmission_policy: RetransmissionPolicy) -> Self {
+        self.retransmission_policy = retransmission_policy;
+        self
+    }
+
+    pub fn max_received_set_size(mut self, max_received_set_size: usize) -> Self {
+        self.max_received_set_size = max_received_set_size;
+        self
+    }
+
+    pub fn max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    /// See [`PeerStateMachine::set_peer_timeout`].
+    pub fn peer_timeout(mut self, peer_timeout: Duration) -> Self {
+        self.peer_timeout = Some(peer_timeout);
+        self
+    }
+
+    pub fn build<I: Timestamp>(self) -> PeerStateMachine<I> {
+        PeerStateMachine {
+            retransmission_policy: self.retransmission_policy,
+            max_received_set_size: self.max_received_set_size,
+            shared: Arc::new(Shared {
+                reliable_index: AtomicU64::new(1),
+                max_packet_size: self.max_packet_size,
+            }),
+            retransmission_map: Default::default(),
+            retransmission_queue: Default::default(),
+            withheld_queue: Default::default(),
+            rate_limiter: None,
+            received_set: Default::default(),
+            total_retransmits: 0,
+            total_packets_sent: 0,
+            total_packets_received: 0,
+            srtt: None,
+            rttvar: Duration::ZERO,
+            peer_timeout: self.peer_timeout,
+            last_received_at: None,
+        }
+    }
+}
+
+/// The state machine for a single peer.
+///
+/// `I` is the type of [`Timestamp`] that the state machine is polled with, which is
+/// [`std::time::Instant`] by default.
+pub struct PeerStateMachine<I = DefaultTimestamp> {
     shared: Arc<Shared>,
-    retransmission_duration: Duration,
-    retransmission_map: FxHashMap<NonZeroU64, Retransmit>,
-    retransmission_queue: VecDeque<NonZeroU64>,
-    received_set: IndexSet<NonZeroU64>,
+    retransmission_policy: RetransmissionPolicy,
+    retransmission_map: ReliableMap<Retransmit<I>>,
+    /// The reliable packets that have been sent, ordered by when they should next be retransmitted.
+    ///
+    /// Entries whose packet is no longer in `retransmission_map` are removed lazily.
+    retransmission_queue: BTreeSet<(I, NonZeroU64)>,
+    /// Packets that have not been sent for the first time due to the rate limiter, in the order
+    /// they were given to the state machine.
+    withheld_queue: VecDeque<Withheld>,
+    rate_limiter: Option<RateLimiter<I>>,
+    received_set: ReceivedSet,
     max_received_set_size: usize,
+    total_retransmits: u64,
+    total_packets_sent: u64,
+    total_packets_received: u64,
+    /// Smoothed round trip time, as described in RFC 6298.
+    srtt: Option<Duration>,
+    /// Round trip time variation, as described in RFC 6298.
+    rttvar: Duration,
+    peer_timeout: Option<Duration>,
+    last_received_at: Option<I>,
 }
 
-impl PeerStateMachine {
+impl<I: Clone> Clone for PeerStateMachine<I> {
+    /// Creates an operationally identical copy of this state machine.
+    ///
+    /// The copy has its own reliable index counter, which starts where this state machine's counter
+    /// currently is. As such, [`PacketBuilder`]s created from this state machine will not advance the
+    /// counter of the copy, so packets for the copy should be built with [`PeerStateMachine::get_packet_builder`]
+    /// on the copy.
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                reliable_index: AtomicU64::new(self.shared.reliable_index.load(Ordering::Relaxed)),
+                max_packet_size: self.shared.max_packet_size,
+            }),
+            retransmission_policy: self.retransmission_policy,
+            retransmission_map: self.retransmission_map.clone(),
+            retransmission_queue: self.retransmission_queue.clone(),
+            withheld_queue: self.withheld_queue.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            received_set: self.received_set.clone(),
+            max_received_set_size: self.max_received_set_size,
+            total_retransmits: self.total_retransmits,
+            total_packets_sent: self.total_packets_sent,
+            total_packets_received: self.total_packets_received,
+            srtt: self.srtt,
+            rttvar: self.rttvar,
+            peer_timeout: self.peer_timeout,
+            last_received_at: self.last_received_at.clone(),
+        }
+    }
+}
+
+/// A snapshot of the counters and buffer sizes of a [`PeerStateMachine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PeerStateMachineStats {
+    /// The number of reliable packets that have not been acknowledged or cancelled yet.
+    pub pending_reliable_count: usize,
+    /// The number of reliable indices from the peer that are remembered to filter duplicates.
+    pub received_set_size: usize,
+    /// The number of times a reliable packet has been retransmitted.
+    pub total_retransmits: u64,
+    /// The number of packets the state machine has recommended to be sent, including acknowledgements
+    /// and retransmissions.
+    pub total_packets_sent: u64,
+    /// The number of packets from the peer given to the state machine, including invalid ones.
+    pub total_packets_received: u64,
+}
+
+impl<I: Timestamp> PeerStateMachine<I> {
     /// Creates a new [`PeerStateMachine`] with the given retransmission duration and maximum received set size.
     ///
-    /// The retransmission duration is the amount of time to wait before retransmitting a packet that has not been
-    /// acknowledged. The maximum received set size should be proportional to the number of reliable packets sent per second,
+    /// The retransmission duration is the minimum amount of time to wait before retransmitting a packet that has not been
+    /// acknowledged. It is raised automatically if the estimated round trip time to the peer is longer. The maximum received set size should be proportional to the number of reliable packets sent per second,
     /// and varies based on the unreliability of the transport layer. If you are intending on sending many reliable packets
     /// over a very unreliable transport layer, you should set this to a higher value, which comes at the cost of approximately
     /// 32 bytes per unit. That is, if `max_received_set_size` is 100, then the received set will consume approximately up to 3200 bytes.
     /// Setting this value too low may cause this peer to acknowledge reliable packets that have already been received (thus handling
     /// them twice).
     ///
+    /// `max_packet_size` is the size of the largest packet the state machine will recommend sending, including the 8 byte
+    /// index trailer, so payloads can be at most `max_packet_size - 8` bytes (see [`PacketBuilder::max_payload_size`]).
+    ///
     /// The returned [`RecommendedAction`] is an action that should be taken immediately after creating the state machine.
     pub fn new(
         retransmission_duration: Duration,
         max_received_set_size: usize,
         max_packet_size: usize,
     ) -> Self {
-        Self {
-            retransmission_duration,
-            max_received_set_size,
-            shared: Arc::new(Shared {
-                reliable_index: AtomicU64::new(1),
-                max_packet_size,
-            }),
-            retransmission_map: Default::default(),
-            retransmission_queue: Default::default(),
-            received_set: Default::default(),
-        }
+        PeerStateMachineBuilder::default()
+            .retransmission_duration(retransmission_duration)
+            .max_received_set_size(max_received_set_size)
+            .max_packet_size(max_packet_size)
+            .build()
     }
 
-    pub fn send_reconnection_msg<'a>(
-        &'a mut self,
-        now: Instant,
-    ) -> (RecommendedAction<'a, 'static>, ReliableIndex) {
+    /// Creates a new [`PeerStateMachine`] with the given [`RetransmissionPolicy`].
+    ///
+    /// See [`PeerStateMachine::new`] for the meaning of the other parameters.
+    pub fn new_with_policy(
+        retransmission_policy: RetransmissionPolicy,
+        max_received_set_size: usize,
+        max_packet_size: usize,
+    ) -> Self {
+        PeerStateMachineBuilder::default()
+            .retransmission_policy(retransmission_policy)
+            .max_received_set_size(max_received_set_size)
+            .max_packet_size(max_packet_size)
+            .build()
+    }
+
+    pub fn send_reconnection_msg(
+        &mut self,
+        now: I,
+    ) -> (RecommendedAction<'_, 'static, I>, ReliableIndex) {
         let index = !(1u64 << 63);
         let data = Box::new(index.to_be_bytes());
         let index = ReliableIndex(NonZeroU64::new(index).unwrap());
@@ -108,23 +346,139 @@ impl PeerStateMachine {
         }
     }
 
+    /// Creates an [`EventualStream`] that sends reliable packets through this state machine,
+    /// cancelling the previous packet whenever a new one is sent.
+    pub fn begin_eventual_stream(&self) -> EventualStream {
+        EventualStream {
+            builder: self.get_packet_builder(),
+            last_index: None,
+        }
+    }
+
+    /// Limits the rate at which this state machine recommends sending packets.
+    ///
+    /// While the rate limiter has no tokens, reliable and unreliable packets are withheld and sent in
+    /// the order they were given once tokens are available, and [`RecommendedAction::WaitForDuration`]
+    /// is returned with the time until the next token is available. Acknowledgements are never limited.
+    pub fn set_rate_limiter(&mut self, limiter: RateLimiter<I>) {
+        self.rate_limiter = Some(limiter);
+    }
+
+    /// Makes the state machine recommend [`RecommendedAction::PeerTimedOut`] if no valid packet has
+    /// been received from the peer for the given duration.
+    ///
+    /// The duration is measured from the last valid packet, or from the first poll after this is
+    /// called if no packet has been received yet.
+    pub fn set_peer_timeout(&mut self, duration: Duration) {
+        self.peer_timeout = Some(duration);
+    }
+
+    /// Removes the peer timeout, if any.
+    pub fn clear_peer_timeout(&mut self) {
+        self.peer_timeout = None;
+    }
+
+    /// Removes the rate limiter, if any, returning it.
+    pub fn take_rate_limiter(&mut self) -> Option<RateLimiter<I>> {
+        self.rate_limiter.take()
+    }
+
     pub fn is_packet_retransmitting(&self, index: ReliableIndex) -> bool {
         self.retransmission_map.contains_key(&index.0)
     }
 
-    /// Digests the given [`Event`] according to the given [`Instant`] and produces a [`RecommendedAction`] that should be taken.
+    /// Returns the number of reliable packets that have not been acknowledged or cancelled yet.
+    pub fn pending_reliable_count(&self) -> usize {
+        self.retransmission_map.len()
+    }
+
+    /// Returns the amount of time since the oldest pending reliable packet was first sent, or `None`
+    /// if there are no pending reliable packets.
+    pub fn oldest_pending_age(&self, now: I) -> Option<Duration> {
+        self.retransmission_map
+            .values()
+            .map(|retransmit| retransmit.sent_at)
+            .min()
+            .map(|sent_at| now.duration_since_or_zero(sent_at))
+    }
+
+    /// Returns the smoothed round trip time to the peer, if any reliable packet has been acknowledged yet.
+    pub fn get_smoothed_rtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    /// Updates the round trip time estimate according to RFC 6298.
+    fn update_rtt(&mut self, sample: Duration) {
+        if let Some(srtt) = self.srtt {
+            let diff = srtt.abs_diff(sample);
+            self.rttvar = (self.rttvar * 3 + diff) / 4;
+            self.srtt = Some((srtt * 7 + sample) / 8);
+        } else {
+            self.srtt = Some(sample);
+            self.rttvar = sample / 2;
+        }
+    }
+
+    /// The amount of time to wait after a packet has been retransmitted `retry_count` times.
+    ///
+    /// The [`RetransmissionPolicy`] acts as a lower bound, which is raised if the estimated
+    /// round trip time is longer.
+    fn retransmission_timeout(&self, retry_count: u32) -> Duration {
+        let delay = self.retransmission_policy.delay(retry_count);
+        if let Some(srtt) = self.srtt {
+            delay.max(srtt + self.rttvar * 4)
+        } else {
+            delay
+        }
+    }
+
+    /// Returns a snapshot of the counters maintained by this state machine.
+    pub fn stats(&self) -> PeerStateMachineStats {
+        PeerStateMachineStats {
+            pending_reliable_count: self.pending_reliable_count(),
+            received_set_size: self.received_set.len(),
+            total_retransmits: self.total_retransmits,
+            total_packets_sent: self.total_packets_sent,
+            total_packets_received: self.total_packets_received,
+        }
+    }
+
+    /// Returns an iterator that polls this state machine with [`Event::NoEvent`] until it recommends
+    /// waiting, yielding every other [`RecommendedAction`] along the way.
+    ///
+    /// Packets that would borrow the state machine are copied, so this is slightly less efficient
+    /// than calling [`PeerStateMachine::poll`] in a loop. The action that ended the iterator can be
+    /// retrieved with [`DrainIter::wait`].
+    pub fn drain(&mut self, now: I) -> DrainIter<'_, I> {
+        DrainIter {
+            state_machine: self,
+            now,
+            wait: None,
+        }
+    }
+
+    /// Digests the given [`Event`] according to the given [`Timestamp`] and produces a [`RecommendedAction`] that should be taken.
     ///
-    /// Strictly speaking, `now` does not need to be the same [`Instant`] across all calls to `poll`. However, it must
-    /// be monotonic across all instances used. Essentially, you can pass a different [`Instant`] to a successive call
+    /// Strictly speaking, `now` does not need to be the same [`Timestamp`] across all calls to `poll`. However, it must
+    /// be monotonic across all instances used. Essentially, you can pass a different [`Timestamp`] to a successive call
     /// to `poll` as it represents a point in the future (you can skip time forward, but not backward).
-    pub fn poll<'a, 'b>(&'a mut self, event: Event<'b>, now: Instant) -> RecommendedAction<'a, 'b> {
+    pub fn poll<'a, 'b>(&'a mut self, event: Event<'b>, now: I) -> RecommendedAction<'a, 'b, I> {
         match event {
             Event::IncomingData(data) => {
+                self.total_packets_received += 1;
                 if data.len() < 8 {
                     return RecommendedAction::HandleError(CakapError::PacketTooSmall);
                 }
 
                 let index = u64::from_be_bytes(data[data.len() - 8..].try_into().unwrap());
+                #[cfg(feature = "tracing")]
+                let _span = tracing::debug_span!(
+                    "incoming_data",
+                    packet_type = packet_type(index),
+                    index,
+                    size = data.len()
+                )
+                .entered();
 
                 if index == !(1 << 63) {
                     // The maximum safe index is 2^63 - 1
@@ -138,6 +492,8 @@ impl PeerStateMachine {
                     // The max index is the least likely index to be in the `received_set`, so
                     // it is a good choice for this purpose.
                     self.received_set.clear();
+                    self.last_received_at = Some(now);
+                    self.total_packets_sent += 1;
                     return RecommendedAction::SendData(HotPacket {
                         inner: HotPacketInner::Index(u64::MAX.to_be_bytes()),
                     });
@@ -146,19 +502,18 @@ impl PeerStateMachine {
                     let msb = index.get() >> 63;
                     if msb == 0 {
                         let reply_index = index | (1 << 63);
+                        self.last_received_at = Some(now);
 
                         // New packet from peer
-                        if self.received_set.insert(index) {
-                            if self.received_set.len() > self.max_received_set_size {
-                                self.received_set.shift_remove_index(0);
-                            }
-
+                        if self.received_set.insert(index, self.max_received_set_size) {
+                            self.total_packets_sent += 1;
                             return RecommendedAction::HandleDataAndSend {
                                 received: &data[0..data.len() - 8],
                                 to_send: reply_index.get().to_be_bytes(),
                             };
                         } else {
                             // Duplicate packet from peer, just acknowledge
+                            self.total_packets_sent += 1;
                             return RecommendedAction::SendData(HotPacket {
                                 inner: HotPacketInner::Index(reply_index.get().to_be_bytes()),
                             });
@@ -169,25 +524,61 @@ impl PeerStateMachine {
                         let Some(true_index) = NonZeroU64::new(true_index) else {
                             return RecommendedAction::HandleError(CakapError::InvalidPacket);
                         };
-                        self.retransmission_map.remove(&true_index);
+                        self.last_received_at = Some(now);
+                        if let Some(retransmit) = self.retransmission_map.remove(&true_index) {
+                            self.retransmission_queue
+                                .remove(&(retransmit.send_at, true_index));
+                            // Acknowledgements of retransmitted packets are ambiguous, so they
+                            // are not used to estimate the round trip time (Karn's algorithm).
+                            if retransmit.retry_count == 0 {
+                                self.update_rtt(now.duration_since_or_zero(retransmit.sent_at));
+                            }
+                        }
                     }
                 } else {
                     // Unreliable packet from peer
+                    self.last_received_at = Some(now);
                     return RecommendedAction::HandleData(&data[0..data.len() - 8]);
                 }
             }
             Event::Action(action) => match action {
+                Action::SendReliable(ReliablePacket { index, data })
+                    if self.rate_limiter.is_some() =>
+                {
+                    // The packet will be sent when the rate limiter allows it
+                    let retransmit = Retransmit {
+                        sent_at: now,
+                        send_at: now,
+                        retry_count: 0,
+                        data,
+                    };
+                    if self
+                        .retransmission_map
+                        .try_insert(index.0, retransmit)
+                        .is_err()
+                    {
+                        return RecommendedAction::HandleError(CakapError::TooManyPendingReliable);
+                    }
+                    self.withheld_queue.push_back(Withheld::Reliable(index.0));
+                }
                 Action::SendReliable(ReliablePacket { index, data }) => {
                     let index = index.0;
-                    let option = self.retransmission_map.insert(
-                        index,
-                        Retransmit {
-                            send_at: now + self.retransmission_duration,
-                            data,
-                        },
-                    );
-                    debug_assert!(option.is_none());
-                    self.retransmission_queue.push_back(index);
+                    let timeout = self.retransmission_timeout(0);
+                    let retransmit = Retransmit {
+                        sent_at: now,
+                        send_at: now + timeout,
+                        retry_count: 0,
+                        data,
+                    };
+                    if self
+                        .retransmission_map
+                        .try_insert(index, retransmit)
+                        .is_err()
+                    {
+                        return RecommendedAction::HandleError(CakapError::TooManyPendingReliable);
+                    }
+                    self.retransmission_queue.insert((now + timeout, index));
+                    self.total_packets_sent += 1;
 
                     return RecommendedAction::SendData(HotPacket {
                         inner: HotPacketInner::Borrowed(
@@ -196,55 +587,210 @@ impl PeerStateMachine {
                     });
                 }
                 Action::CancelReliable(ReliableIndex(index)) => {
-                    self.retransmission_map.remove(&index);
+                    if let Some(retransmit) = self.retransmission_map.remove(&index) {
+                        self.retransmission_queue
+                            .remove(&(retransmit.send_at, index));
+                    }
                 }
                 Action::CancelAllReliable => {
                     self.retransmission_map.clear();
                     self.retransmission_queue.clear();
+                    self.withheld_queue
+                        .retain(|withheld| matches!(withheld, Withheld::Unreliable(_)));
+                }
+                Action::DrainReliable => {
+                    let mut drained: Vec<_> =
+                        core::mem::take(&mut self.retransmission_queue)
+                            .into_iter()
+                            .map(|(_, index)| index)
+                            .chain(self.withheld_queue.iter().filter_map(
+                                |withheld| match withheld {
+                                    Withheld::Reliable(index) => Some(*index),
+                                    Withheld::Unreliable(_) => None,
+                                },
+                            ))
+                            .filter(|index| self.retransmission_map.remove(index).is_some())
+                            .map(ReliableIndex)
+                            .collect();
+                    self.withheld_queue
+                        .retain(|withheld| matches!(withheld, Withheld::Unreliable(_)));
+                    // Reliable indices increase monotonically, so this is the order they were created in
+                    drained.sort_unstable_by_key(|index| index.0);
+                    return RecommendedAction::DrainedReliable(drained);
+                }
+                Action::DrainOldReliable { max_age } => {
+                    let mut drained = Vec::new();
+                    self.retransmission_map.retain(|&index, retransmit| {
+                        if now.duration_since_or_zero(retransmit.sent_at) > max_age {
+                            drained.push(ReliableIndex(index));
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                    // The queues are cleaned up lazily when polled
+                    drained.sort_unstable_by_key(|index| index.0);
+                    return RecommendedAction::DrainedReliable(drained);
+                }
+                Action::SendUnreliable(UnreliablePacket { data })
+                    if self.rate_limiter.is_some() =>
+                {
+                    // The packet will be sent when the rate limiter allows it
+                    self.withheld_queue.push_back(Withheld::Unreliable(data));
                 }
                 Action::SendUnreliable(UnreliablePacket { data }) => {
+                    self.total_packets_sent += 1;
                     return RecommendedAction::SendData(HotPacket {
                         inner: HotPacketInner::Owned(data),
-                    })
+                    });
                 }
             },
             Event::NoEvent => {}
         }
+        let mut peer_timeout_deadline = None;
+        if let Some(peer_timeout) = self.peer_timeout {
+            let last_received_at = *self.last_received_at.get_or_insert(now);
+            let deadline = last_received_at + peer_timeout;
+            if deadline <= now {
+                // Restart the timer so that the timeout is only reported once per period of silence
+                self.last_received_at = Some(now);
+                return RecommendedAction::PeerTimedOut;
+            }
+            peer_timeout_deadline = Some(deadline);
+        }
+        let wait_until = |deadline: Option<I>| match (deadline, peer_timeout_deadline) {
+            (Some(deadline), Some(peer_timeout_deadline)) => {
+                RecommendedAction::WaitForDuration(deadline.min(peer_timeout_deadline))
+            }
+            (Some(deadline), None) | (None, Some(deadline)) => {
+                RecommendedAction::WaitForDuration(deadline)
+            }
+            (None, None) => RecommendedAction::WaitForData,
+        };
+        while let Some(withheld) = self.withheld_queue.front() {
+            if let Withheld::Reliable(index) = withheld {
+                if !self.retransmission_map.contains_key(index) {
+                    self.withheld_queue.pop_front();
+                    continue;
+                }
+            }
+            if let Some(rate_limiter) = &mut self.rate_limiter {
+                if let Err(duration) = rate_limiter.try_acquire(now) {
+                    return wait_until(Some(now + duration));
+                }
+            }
+            let first_index = match self.withheld_queue.pop_front().unwrap() {
+                Withheld::Reliable(index) => index,
+                Withheld::Unreliable(data) => {
+                    self.total_packets_sent += 1;
+                    return RecommendedAction::SendData(HotPacket {
+                        inner: HotPacketInner::Owned(data),
+                    });
+                }
+            };
+            let timeout = self.retransmission_timeout(0);
+            let retransmit = self.retransmission_map.get_mut(&first_index).unwrap();
+            retransmit.sent_at = now;
+            retransmit.send_at = now + timeout;
+            self.retransmission_queue
+                .insert((now + timeout, first_index));
+            self.total_packets_sent += 1;
+            // To please the borrow checker
+            let retransmit = self.retransmission_map.get(&first_index).unwrap();
+            return RecommendedAction::SendData(HotPacket {
+                inner: HotPacketInner::Borrowed(&retransmit.data),
+            });
+        }
         loop {
-            let Some(&first_index) = self.retransmission_queue.front() else {
-                break RecommendedAction::WaitForData;
+            let Some(&(send_at, first_index)) = self.retransmission_queue.first() else {
+                break wait_until(None);
             };
-            let Some(retransmit) = self.retransmission_map.get_mut(&first_index) else {
-                self.retransmission_queue.pop_front();
+            let Some(retransmit) = self.retransmission_map.get(&first_index) else {
+                self.retransmission_queue.pop_first();
                 continue;
             };
-            if retransmit.send_at <= now {
-                self.retransmission_queue.pop_front();
-                self.retransmission_queue.push_back(first_index);
-                retransmit.send_at = now + self.retransmission_duration;
+            if retransmit.send_at != send_at {
+                // The packet has been rescheduled since this entry was made
+                self.retransmission_queue.pop_first();
+                continue;
+            }
+            if send_at <= now {
+                let retry_count = retransmit.retry_count + 1;
+                let timeout = self.retransmission_timeout(retry_count);
+                let retransmit = self.retransmission_map.get_mut(&first_index).unwrap();
+                if self
+                    .retransmission_policy
+                    .is_expired(retransmit.retry_count)
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        index = first_index,
+                        retry_count = retransmit.retry_count,
+                        "Reliable packet expired"
+                    );
+                    self.retransmission_map.remove(&first_index);
+                    self.retransmission_queue.pop_first();
+                    break RecommendedAction::ReliableExpired(ReliableIndex(first_index));
+                }
+                if let Some(rate_limiter) = &mut self.rate_limiter {
+                    if let Err(duration) = rate_limiter.try_acquire(now) {
+                        break wait_until(Some(now + duration));
+                    }
+                }
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    index = first_index,
+                    retry_count,
+                    "Retransmitting reliable packet"
+                );
+                self.retransmission_queue.pop_first();
+                self.retransmission_queue
+                    .insert((now + timeout, first_index));
+                retransmit.retry_count = retry_count;
+                retransmit.send_at = now + timeout;
+                self.total_retransmits += 1;
+                self.total_packets_sent += 1;
                 // To please the borrow checker
                 let retransmit = self.retransmission_map.get(&first_index).unwrap();
                 break RecommendedAction::SendData(HotPacket {
                     inner: HotPacketInner::Borrowed(&retransmit.data),
                 });
             } else {
-                break RecommendedAction::WaitForDuration(retransmit.send_at - now);
+                break wait_until(Some(send_at));
             }
         }
     }
 }
 
+/// Describes the kind of packet with the given index trailer, for tracing.
+#[cfg(feature = "tracing")]
+fn packet_type(index: u64) -> &'static str {
+    if index == !(1 << 63) {
+        "reconnection"
+    } else if index == 0 {
+        "unreliable"
+    } else if index >> 63 == 0 {
+        "reliable"
+    } else {
+        "acknowledgement"
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
-pub enum RecommendedAction<'a, 'b> {
+pub enum RecommendedAction<'a, 'b, I = DefaultTimestamp> {
     /// Wait indefinitely until data from the peer is received, or there is data to send.
     WaitForData,
-    /// Wait at most the given [`Duration`] for data from the peer, or data to be sent.
+    /// Wait until at most the given deadline for data from the peer, or data to be sent.
     ///
-    /// If the given duration is 5 seconds, the event loop should still poll the state
+    /// If the deadline is 5 seconds away, the event loop should still poll the state
     /// machine if there is data to be sent, or data was received from the peer. However,
-    /// if 5 seconds have passed and neither event occurs, the state machine should still
+    /// if the deadline passes and neither event occurs, the state machine should still
     /// be polled anyway.
-    WaitForDuration(Duration),
+    ///
+    /// The deadline is absolute so that handling this action late does not delay the next
+    /// retransmission. With [`std::time::Instant`], the time to sleep can be computed with
+    /// `deadline.saturating_duration_since(Instant::now())`.
+    WaitForDuration(I),
     /// Handle the given error (by logging or otherwise) and poll the state machine again
     /// with `NoEvent`.
     HandleError(CakapError),
@@ -260,11 +806,47 @@ pub enum RecommendedAction<'a, 'b> {
     },
     /// Send the given data to the peer.
     SendData(HotPacket<'a>),
+    /// The reliable packet with the given index was retransmitted the maximum number of times
+    /// allowed by the [`RetransmissionPolicy`] without being acknowledged, and has been abandoned.
+    ///
+    /// Handle this (by logging or otherwise) and poll the state machine again with `NoEvent`.
+    ReliableExpired(ReliableIndex),
+    /// The reliable packets with the given indices were cancelled due to [`Action::DrainReliable`] or
+    /// [`Action::DrainOldReliable`], in the order they were created.
+    ///
+    /// Re-send whichever messages are still relevant and poll the state machine again with `NoEvent`.
+    DrainedReliable(Vec<ReliableIndex>),
+    /// No valid packet has been received from the peer within the duration given to
+    /// [`PeerStateMachine::set_peer_timeout`].
+    ///
+    /// Handle this (by reconnecting or otherwise) and poll the state machine again with `NoEvent`.
+    /// The timeout is restarted, so this will be recommended again if the peer stays silent.
+    PeerTimedOut,
 }
 
-impl<'a, 'b> RecommendedAction<'a, 'b> {
-    #[cfg(test)]
-    fn get_hot_packet(&self) -> &HotPacket<'a> {
+impl<'a, 'b, I> RecommendedAction<'a, 'b, I> {
+    /// Copies any data borrowed from the state machine, so that this action no longer borrows it.
+    pub fn into_owned(self) -> RecommendedAction<'static, 'b, I> {
+        match self {
+            Self::WaitForData => RecommendedAction::WaitForData,
+            Self::WaitForDuration(deadline) => RecommendedAction::WaitForDuration(deadline),
+            Self::HandleError(e) => RecommendedAction::HandleError(e),
+            Self::HandleData(data) => RecommendedAction::HandleData(data),
+            Self::HandleDataAndSend { received, to_send } => {
+                RecommendedAction::HandleDataAndSend { received, to_send }
+            }
+            Self::SendData(hot_packet) => RecommendedAction::SendData(hot_packet.into_owned()),
+            Self::ReliableExpired(index) => RecommendedAction::ReliableExpired(index),
+            Self::DrainedReliable(indices) => RecommendedAction::DrainedReliable(indices),
+            Self::PeerTimedOut => RecommendedAction::PeerTimedOut,
+        }
+    }
+
+    #[cfg(all(test, feature = "std"))]
+    fn get_hot_packet(&self) -> &HotPacket<'a>
+    where
+        I: core::fmt::Debug,
+    {
         match self {
             Self::SendData(hot_packet) => hot_packet,
             _ => panic!("Expected SendData, got {:?}", self),
@@ -272,6 +854,45 @@ impl<'a, 'b> RecommendedAction<'a, 'b> {
     }
 }
 
+/// An iterator over the actions recommended by a [`PeerStateMachine`] until it recommends waiting.
+///
+/// Created by [`PeerStateMachine::drain`].
+pub struct DrainIter<'a, I = DefaultTimestamp> {
+    state_machine: &'a mut PeerStateMachine<I>,
+    now: I,
+    wait: Option<RecommendedAction<'static, 'static, I>>,
+}
+
+impl<'a, I> DrainIter<'a, I> {
+    /// Returns the [`RecommendedAction::WaitForData`] or [`RecommendedAction::WaitForDuration`] that
+    /// ended this iterator, or `None` if it has not ended yet.
+    pub fn wait(&self) -> Option<&RecommendedAction<'static, 'static, I>> {
+        self.wait.as_ref()
+    }
+}
+
+impl<'a, I: Timestamp> Iterator for DrainIter<'a, I> {
+    type Item = RecommendedAction<'a, 'static, I>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.wait.is_some() {
+            return None;
+        }
+        match self
+            .state_machine
+            .poll(Event::NoEvent, self.now)
+            .into_owned()
+        {
+            action @ (RecommendedAction::WaitForData | RecommendedAction::WaitForDuration(_)) => {
+                self.wait = Some(action);
+                None
+            }
+            action => Some(action),
+        }
+    }
+}
+
+#[derive(Default)]
 pub enum Event<'a> {
     /// A whole packet of data, with no padding bytes or otherwise empty space.
     IncomingData(&'a [u8]),
@@ -279,24 +900,19 @@ pub enum Event<'a> {
     Action(Action),
     /// No data received, to be sent. Usually used when some duration of time has passed,
     /// or after an error was handled.
+    #[default]
     NoEvent,
 }
 
-impl<'a> Default for Event<'a> {
-    fn default() -> Self {
-        Self::NoEvent
-    }
-}
-
 impl<'a> From<Action> for Event<'a> {
     fn from(value: Action) -> Self {
         Self::Action(value)
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
-    use std::ops::Deref;
+    use std::{ops::Deref, time::Instant};
 
     use super::*;
 
@@ -350,10 +966,15 @@ mod tests {
         );
         // `state_machine` is notified that the packet is sent
         let action = state_machine.poll(Event::NoEvent, Instant::now());
-        let RecommendedAction::WaitForDuration(duration) = action else {
+        let RecommendedAction::WaitForDuration(deadline) = action else {
             panic!("Not WaitForDuration")
         };
-        assert!(duration.as_millis() > 98);
+        assert!(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                > 98
+        );
 
         let mut other_state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
         // `other_state_machine` receives the reliable packet
@@ -378,10 +999,15 @@ mod tests {
 
         // `state_machine` waits for data
         let action = state_machine.poll(Event::NoEvent, Instant::now());
-        let RecommendedAction::WaitForDuration(duration) = action else {
+        let RecommendedAction::WaitForDuration(deadline) = action else {
             panic!("Not WaitForDuration")
         };
-        assert!(duration.as_millis() > 98);
+        assert!(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                > 98
+        );
 
         // `state_machine` receives the acknowledgement
         let event = Event::IncomingData(&to_send);
@@ -410,10 +1036,15 @@ mod tests {
         );
         // `state_machine` is notified that the packet is sent
         let action = state_machine.poll(Event::NoEvent, Instant::now());
-        let RecommendedAction::WaitForDuration(duration) = action else {
+        let RecommendedAction::WaitForDuration(deadline) = action else {
             panic!("Not WaitForDuration")
         };
-        assert!(duration.as_millis() > 98);
+        assert!(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                > 98
+        );
 
         let mut other_state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
         // `other_state_machine` receives the reliable packet
@@ -438,10 +1069,15 @@ mod tests {
 
         // `state_machine` waits for data
         let action = state_machine.poll(Event::NoEvent, Instant::now());
-        let RecommendedAction::WaitForDuration(duration) = action else {
+        let RecommendedAction::WaitForDuration(deadline) = action else {
             panic!("Not WaitForDuration")
         };
-        assert!(duration.as_millis() > 98);
+        assert!(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                > 98
+        );
 
         // 'state_machine' retransmits after some time
         let action =
@@ -452,10 +1088,15 @@ mod tests {
         );
         // `state_machine` is notified that the packet is sent
         let action = state_machine.poll(Event::NoEvent, Instant::now());
-        let RecommendedAction::WaitForDuration(duration) = action else {
+        let RecommendedAction::WaitForDuration(deadline) = action else {
             panic!("Not WaitForDuration")
         };
-        assert!(duration.as_millis() > 98);
+        assert!(
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_millis()
+                > 98
+        );
 
         // `other_state_machine` receives the data
         let event = Event::IncomingData(&[15, 0, 0, 0, 0, 0, 0, 0, 1]);
@@ -470,4 +1111,626 @@ mod tests {
 
         assert_eq!(action, RecommendedAction::WaitForData);
     }
+
+    #[test]
+    fn exponential_backoff_1() {
+        let mut state_machine = PeerStateMachine::new_with_policy(
+            RetransmissionPolicy::ExponentialBackoff {
+                base: Duration::from_millis(100),
+                multiplier: 2.0,
+                max_exponent: 3,
+                cap: Duration::from_secs(1),
+                max_retries: Some(2),
+            },
+            256,
+            1400,
+        );
+        let reliable_builder = state_machine.get_packet_builder();
+        let outgoing_data = reliable_builder
+            .new_reliable([15].into_iter().collect())
+            .unwrap();
+        let index = outgoing_data.get_index();
+        let start = Instant::now();
+
+        // `state_machine` sends a reliable packet
+        let action = state_machine.poll(Event::Action(outgoing_data.into()), start);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [15, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, start),
+            RecommendedAction::WaitForDuration(start + Duration::from_millis(100))
+        );
+
+        // First retransmission doubles the wait
+        let now = start + Duration::from_millis(100);
+        let action = state_machine.poll(Event::NoEvent, now);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [15, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(200))
+        );
+
+        // Second retransmission doubles it again
+        let now = now + Duration::from_millis(200);
+        let action = state_machine.poll(Event::NoEvent, now);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [15, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(400))
+        );
+
+        // The packet is abandoned after the maximum number of retries
+        let now = now + Duration::from_millis(400);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::ReliableExpired(index)
+        );
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForData
+        );
+        assert!(!state_machine.is_packet_retransmitting(index));
+    }
+
+    #[test]
+    fn exponential_backoff_2() {
+        let mut state_machine = PeerStateMachine::new_with_policy(
+            RetransmissionPolicy::ExponentialBackoff {
+                base: Duration::from_millis(100),
+                multiplier: 2.0,
+                max_exponent: 3,
+                cap: Duration::from_secs(1),
+                max_retries: None,
+            },
+            256,
+            1400,
+        );
+        let reliable_builder = state_machine.get_packet_builder();
+        let start = Instant::now();
+
+        // `state_machine` sends packet A, and retransmits it once
+        let packet_a = reliable_builder
+            .new_reliable([15].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(packet_a.into()), start);
+        let now = start + Duration::from_millis(100);
+        let action = state_machine.poll(Event::NoEvent, now);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [15, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+
+        // `state_machine` sends packet B, whose first timeout ends before A's second
+        let packet_b = reliable_builder
+            .new_reliable([16].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(packet_b.into()), now);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(100))
+        );
+
+        // B is retransmitted first, even though A was queued before it
+        let now = now + Duration::from_millis(100);
+        let action = state_machine.poll(Event::NoEvent, now);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [16, 0, 0, 0, 0, 0, 0, 0, 2],
+        );
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(start + Duration::from_millis(300))
+        );
+
+        let now = start + Duration::from_millis(300);
+        let action = state_machine.poll(Event::NoEvent, now);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [15, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(start + Duration::from_millis(400))
+        );
+    }
+
+    #[test]
+    fn stats_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let outgoing_data = reliable_builder
+            .new_reliable([15].into_iter().collect())
+            .unwrap();
+        let now = Instant::now();
+
+        state_machine.poll(Event::Action(outgoing_data.into()), now);
+        state_machine.poll(Event::NoEvent, now + Duration::from_millis(100));
+        state_machine.poll(Event::IncomingData(&[16, 0, 0, 0, 0, 0, 0, 0, 1]), now);
+
+        assert_eq!(
+            state_machine.stats(),
+            PeerStateMachineStats {
+                pending_reliable_count: 1,
+                received_set_size: 1,
+                total_retransmits: 1,
+                total_packets_sent: 3,
+                total_packets_received: 1,
+            }
+        );
+
+        // `state_machine` receives the acknowledgement
+        let to_send = (1u64 + (1 << 63)).to_be_bytes();
+        state_machine.poll(Event::IncomingData(&to_send), now);
+
+        let stats = state_machine.stats();
+        assert_eq!(stats.pending_reliable_count, 0);
+        assert_eq!(stats.total_packets_received, 2);
+    }
+
+    #[test]
+    fn eventual_stream_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let mut stream = state_machine.begin_eventual_stream();
+        let now = Instant::now();
+
+        let action = stream
+            .send(&mut state_machine, vec![1].into(), now)
+            .unwrap();
+        assert_eq!(action.get_hot_packet().deref(), [1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let first_index = stream.get_last_index().unwrap();
+
+        let action = stream
+            .send(&mut state_machine, vec![2].into(), now)
+            .unwrap();
+        assert_eq!(action.get_hot_packet().deref(), [2, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+        // Only the latest packet is retransmitted
+        assert!(!state_machine.is_packet_retransmitting(first_index));
+        assert!(state_machine.is_packet_retransmitting(stream.get_last_index().unwrap()));
+        let action = state_machine.poll(Event::NoEvent, now + Duration::from_millis(100));
+        assert_eq!(action.get_hot_packet().deref(), [2, 0, 0, 0, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn rtt_estimation_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+        assert_eq!(state_machine.get_smoothed_rtt(), None);
+
+        let outgoing_data = reliable_builder
+            .new_reliable([15].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(outgoing_data.into()), now);
+
+        // `state_machine` receives the acknowledgement after 40ms
+        let to_send = (1u64 + (1 << 63)).to_be_bytes();
+        let now = now + Duration::from_millis(40);
+        state_machine.poll(Event::IncomingData(&to_send), now);
+        assert_eq!(
+            state_machine.get_smoothed_rtt(),
+            Some(Duration::from_millis(40))
+        );
+
+        // The retransmission timeout is now srtt + 4 * rttvar = 40ms + 4 * 20ms
+        let outgoing_data = reliable_builder
+            .new_reliable([16].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(outgoing_data.into()), now);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(120))
+        );
+    }
+
+    #[test]
+    fn pending_reliable_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+        assert_eq!(state_machine.pending_reliable_count(), 0);
+        assert_eq!(state_machine.oldest_pending_age(now), None);
+
+        let outgoing_data = reliable_builder
+            .new_reliable([15].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(outgoing_data.into()), now);
+        let outgoing_data = reliable_builder
+            .new_reliable([16].into_iter().collect())
+            .unwrap();
+        state_machine.poll(
+            Event::Action(outgoing_data.into()),
+            now + Duration::from_millis(30),
+        );
+
+        assert_eq!(state_machine.pending_reliable_count(), 2);
+        assert_eq!(
+            state_machine.oldest_pending_age(now + Duration::from_millis(50)),
+            Some(Duration::from_millis(50))
+        );
+    }
+
+    #[test]
+    fn clone_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+
+        let outgoing_data = reliable_builder
+            .new_reliable([15].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(outgoing_data.into()), now);
+        state_machine.poll(Event::IncomingData(&[16, 0, 0, 0, 0, 0, 0, 0, 1]), now);
+
+        let mut restored = state_machine.clone();
+        assert_eq!(restored.stats(), state_machine.stats());
+
+        // The clone continues the reliable index from where the original was
+        let outgoing_data = restored
+            .get_packet_builder()
+            .new_reliable([17].into_iter().collect())
+            .unwrap();
+        assert_eq!(outgoing_data.get_index().0.get(), 2);
+
+        // The clone retransmits the pending packet
+        let action = restored.poll(Event::NoEvent, now + Duration::from_millis(100));
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            [15, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+
+        // The clone remembers the received packet
+        let action = restored.poll(Event::IncomingData(&[16, 0, 0, 0, 0, 0, 0, 0, 1]), now);
+        assert_eq!(
+            action.get_hot_packet().deref(),
+            (1u64 + (1 << 63)).to_be_bytes()
+        );
+
+        // The original is unaffected
+        assert_eq!(
+            reliable_builder
+                .new_reliable([18].into_iter().collect())
+                .unwrap()
+                .get_index()
+                .0
+                .get(),
+            2
+        );
+    }
+
+    #[test]
+    fn rate_limiter_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        state_machine.set_rate_limiter(RateLimiter::new(10.0, 2));
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+
+        // The burst allows two packets immediately
+        for i in 0..2 {
+            let outgoing_data = reliable_builder
+                .new_unreliable([i].into_iter().collect())
+                .unwrap();
+            let action = state_machine.poll(Event::Action(outgoing_data.into()), now);
+            assert_eq!(action.get_hot_packet().deref(), [i, 0, 0, 0, 0, 0, 0, 0, 0]);
+        }
+
+        // The third unreliable packet is withheld until a token is available
+        let outgoing_data = reliable_builder
+            .new_unreliable([2].into_iter().collect())
+            .unwrap();
+        assert_eq!(
+            state_machine.poll(Event::Action(outgoing_data.into()), now),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(100))
+        );
+
+        // So is a reliable packet, which is sent after the unreliable one
+        let outgoing_data = reliable_builder
+            .new_reliable([3].into_iter().collect())
+            .unwrap();
+        assert_eq!(
+            state_machine.poll(Event::Action(outgoing_data.into()), now),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(100))
+        );
+        let action = state_machine.poll(Event::NoEvent, now + Duration::from_millis(100));
+        assert_eq!(action.get_hot_packet().deref(), [2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now + Duration::from_millis(100)),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(200))
+        );
+        let action = state_machine.poll(Event::NoEvent, now + Duration::from_millis(200));
+        assert_eq!(action.get_hot_packet().deref(), [3, 0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn rate_limiter_2() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        state_machine.set_rate_limiter(RateLimiter::new(10.0, 2));
+        let builder = state_machine.get_packet_builder();
+        let mut now = Instant::now();
+
+        let mut sent = vec![];
+        for i in 0..10 {
+            let outgoing_data = builder.new_unreliable([i].into_iter().collect()).unwrap();
+            let action = state_machine.poll(Event::Action(outgoing_data.into()), now);
+            if let RecommendedAction::SendData(packet) = action {
+                sent.push(packet[0]);
+            }
+        }
+        // Every packet in the burst is eventually sent, in order
+        while sent.len() < 10 {
+            match state_machine.poll(Event::NoEvent, now) {
+                RecommendedAction::SendData(packet) => sent.push(packet[0]),
+                RecommendedAction::WaitForDuration(deadline) => now = deadline,
+                action => panic!("Unexpected action: {action:?}"),
+            }
+        }
+        assert_eq!(sent, (0..10).collect::<Vec<_>>());
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForData
+        );
+    }
+
+    #[test]
+    fn drain_reliable_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+
+        let mut indices = vec![];
+        for i in 0..3 {
+            let outgoing_data = reliable_builder
+                .new_reliable([i].into_iter().collect())
+                .unwrap();
+            indices.push(outgoing_data.get_index());
+            state_machine.poll(Event::Action(outgoing_data.into()), now);
+        }
+        state_machine.poll(Event::Action(Action::CancelReliable(indices[1])), now);
+        // Rotates the retransmission queue
+        state_machine.poll(Event::NoEvent, now + Duration::from_millis(100));
+
+        assert_eq!(
+            state_machine.poll(Event::Action(Action::DrainReliable), now),
+            RecommendedAction::DrainedReliable(vec![indices[0], indices[2]])
+        );
+        assert_eq!(state_machine.pending_reliable_count(), 0);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForData
+        );
+    }
+
+    #[test]
+    fn peer_timeout_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        state_machine.set_peer_timeout(Duration::from_secs(1));
+        let now = Instant::now();
+
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(now + Duration::from_secs(1))
+        );
+        let now = now + Duration::from_millis(600);
+        assert_eq!(
+            state_machine.poll(Event::IncomingData(&[1, 0, 0, 0, 0, 0, 0, 0, 0]), now),
+            RecommendedAction::HandleData(&[1])
+        );
+
+        let reliable_builder = state_machine.get_packet_builder();
+        let outgoing_data = reliable_builder
+            .new_reliable([2].into_iter().collect())
+            .unwrap();
+        state_machine.poll(Event::Action(outgoing_data.into()), now);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now + Duration::from_millis(50)),
+            RecommendedAction::WaitForDuration(now + Duration::from_millis(100))
+        );
+
+        // Retransmissions do not count as receiving data from the peer
+        let now = now + Duration::from_millis(950);
+        state_machine.poll(Event::NoEvent, now);
+        let now = now + Duration::from_millis(50);
+        assert_eq!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::PeerTimedOut
+        );
+        assert!(matches!(
+            state_machine.poll(Event::NoEvent, now),
+            RecommendedAction::WaitForDuration(_)
+        ));
+    }
+
+    #[test]
+    fn drain_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+
+        for i in 0..2 {
+            let outgoing_data = reliable_builder
+                .new_reliable([i].into_iter().collect())
+                .unwrap();
+            state_machine.poll(Event::Action(outgoing_data.into()), now);
+        }
+
+        let now = now + Duration::from_millis(100);
+        let mut drain = state_machine.drain(now);
+        let packets: Vec<_> = (&mut drain)
+            .map(|action| action.get_hot_packet().to_vec())
+            .collect();
+        assert_eq!(
+            packets,
+            [[0, 0, 0, 0, 0, 0, 0, 0, 1], [1, 0, 0, 0, 0, 0, 0, 0, 2]]
+        );
+        assert_eq!(
+            drain.wait(),
+            Some(&RecommendedAction::WaitForDuration(
+                now + Duration::from_millis(100)
+            ))
+        );
+        assert_eq!(drain.next(), None);
+    }
+
+    #[test]
+    fn builder_1() {
+        let state_machine: PeerStateMachine = PeerStateMachineBuilder::default()
+            .retransmission_duration(Duration::from_millis(100))
+            .max_packet_size(12)
+            .build();
+        let builder = state_machine.get_packet_builder();
+        assert_eq!(builder.max_payload_size(), 4);
+        assert!(builder.check_payload_size(4).is_ok());
+        assert_eq!(
+            builder.check_payload_size(5),
+            Err(CakapError::PayloadTooLarge {
+                actual_size: 5,
+                max_size: 4
+            })
+        );
+        assert!(!builder.check_payload_size(5).unwrap_err().is_fatal());
+        assert!(builder.new_reliable([0; 4].into_iter().collect()).is_ok());
+        let Err(error::BuildPacketError::PayloadTooLarge { buffer, error }) =
+            builder.new_reliable([0; 5].into_iter().collect())
+        else {
+            panic!("Payload should be too large");
+        };
+        assert_eq!(buffer, [0; 5]);
+        assert_eq!(
+            error,
+            CakapError::PayloadTooLarge {
+                actual_size: 5,
+                max_size: 4
+            }
+        );
+    }
+
+    #[test]
+    fn max_payload_size_1() {
+        let state_machine: PeerStateMachine =
+            PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let builder = state_machine.get_packet_builder();
+        let max = builder.max_payload_size();
+        assert_eq!(max, 1392);
+
+        let packet = builder.new_unreliable(vec![0; max].into()).unwrap();
+        assert_eq!(packet.data.len(), 1400);
+        let packet = builder.new_reliable(vec![0; max].into()).unwrap();
+        assert_eq!(packet.data.len(), 1400);
+
+        assert!(matches!(
+            builder.new_unreliable(vec![0; max + 1].into()),
+            Err(error::BuildPacketError::PayloadTooLarge { .. })
+        ));
+        assert!(matches!(
+            builder.new_reliable(vec![0; max + 1].into()),
+            Err(error::BuildPacketError::PayloadTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn drain_old_reliable_1() {
+        let mut state_machine = PeerStateMachine::new(Duration::from_millis(100), 256, 1400);
+        let reliable_builder = state_machine.get_packet_builder();
+        let now = Instant::now();
+
+        let mut indices = vec![];
+        for i in 0..3 {
+            let outgoing_data = reliable_builder
+                .new_reliable([i].into_iter().collect())
+                .unwrap();
+            indices.push(outgoing_data.get_index());
+            state_machine.poll(
+                Event::Action(outgoing_data.into()),
+                now + Duration::from_secs(i as u64),
+            );
+        }
+        let now = now + Duration::from_secs(5);
+
+        assert_eq!(
+            state_machine.poll(
+                Event::Action(Action::DrainOldReliable {
+                    max_age: Duration::from_millis(3500)
+                }),
+                now
+            ),
+            RecommendedAction::DrainedReliable(vec![indices[0], indices[1]])
+        );
+        assert_eq!(state_machine.pending_reliable_count(), 1);
+        assert!(state_machine.is_packet_retransmitting(indices[2]));
+        assert_eq!(
+            state_machine
+                .poll(Event::NoEvent, now)
+                .get_hot_packet()
+                .deref(),
+            [2, 0, 0, 0, 0, 0, 0, 0, 3]
+        );
+    }
+
+    /// Encodes a single byte as itself.
+    struct ByteCodec;
+
+    impl typed::Codec<u8, u8> for ByteCodec {
+        type Error = ();
+
+        fn encode(&mut self, msg: &u8) -> Vec<u8> {
+            vec![*msg]
+        }
+
+        fn decode(&mut self, bytes: &[u8]) -> Result<u8, ()> {
+            match bytes {
+                [byte] => Ok(*byte),
+                _ => Err(()),
+            }
+        }
+    }
+
+    #[test]
+    fn typed_1() {
+        use typed::{TypedPeerStateMachine, TypedRecommendedAction};
+
+        let mut state_machine = TypedPeerStateMachine::new(
+            PeerStateMachine::new(Duration::from_millis(100), 256, 1400),
+            ByteCodec,
+        );
+        let now = Instant::now();
+
+        let (action, index) = state_machine.send_reliable(&15, now).unwrap();
+        assert_eq!(index.0.get(), 1);
+        let TypedRecommendedAction::SendData(hot_packet) = action else {
+            panic!("Not SendData")
+        };
+        assert_eq!(hot_packet.deref(), [15, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let action = state_machine.poll(Event::IncomingData(&[16, 0, 0, 0, 0, 0, 0, 0, 1]), now);
+        assert_eq!(
+            action,
+            TypedRecommendedAction::HandleMessageAndSend {
+                message: 16,
+                to_send: (1u64 + (1 << 63)).to_be_bytes()
+            }
+        );
+
+        let action =
+            state_machine.poll(Event::IncomingData(&[16, 17, 0, 0, 0, 0, 0, 0, 0, 0]), now);
+        assert_eq!(action, TypedRecommendedAction::DecodeError(()));
+
+        // Reliable data that cannot be decoded is still acknowledged
+        let action =
+            state_machine.poll(Event::IncomingData(&[16, 17, 0, 0, 0, 0, 0, 0, 0, 2]), now);
+        assert_eq!(
+            action,
+            TypedRecommendedAction::DecodeErrorAndSend {
+                error: (),
+                to_send: (2u64 + (1 << 63)).to_be_bytes()
+            }
+        );
+    }
 }
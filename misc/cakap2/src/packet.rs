@@ -1,17 +1,26 @@
-use std::{
-    fmt::Debug,
-    num::NonZeroU64,
-    ops::Deref,
-    sync::{atomic::Ordering, Arc},
-};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{fmt::Debug, num::NonZeroU64, ops::Deref, sync::atomic::Ordering, time::Duration};
 
-use crate::{error::BuildPacketError, Shared};
+use crate::{
+    error::{BuildPacketError, CakapError},
+    Shared,
+};
 
 #[derive(Debug)]
 pub enum Action {
     SendReliable(ReliablePacket),
     CancelReliable(ReliableIndex),
     CancelAllReliable,
+    /// Cancels all reliable packets like [`Action::CancelAllReliable`], but the state machine will
+    /// respond with [`RecommendedAction::DrainedReliable`](crate::RecommendedAction::DrainedReliable)
+    /// containing the indices of the cancelled packets.
+    DrainReliable,
+    /// Cancels all reliable packets that were first sent more than `max_age` ago, and the state machine
+    /// will respond with [`RecommendedAction::DrainedReliable`](crate::RecommendedAction::DrainedReliable)
+    /// containing the indices of the cancelled packets.
+    DrainOldReliable {
+        max_age: Duration,
+    },
     SendUnreliable(UnreliablePacket),
 }
 
@@ -44,7 +53,7 @@ pub struct UnreliablePacket {
     pub(crate) data: Box<[u8]>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ReliableIndex(pub(crate) NonZeroU64);
 
 pub struct PacketBody {
@@ -64,6 +73,14 @@ impl From<Vec<u8>> for PacketBody {
     }
 }
 
+impl From<Box<[u8]>> for PacketBody {
+    fn from(data: Box<[u8]>) -> Self {
+        Self {
+            data: data.into_vec(),
+        }
+    }
+}
+
 impl FromIterator<u8> for PacketBody {
     fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
         Self {
@@ -79,19 +96,38 @@ pub struct PacketBuilder {
 }
 
 impl PacketBuilder {
+    /// The largest payload that can be given to [`PacketBuilder::new_reliable`] and
+    /// [`PacketBuilder::new_unreliable`], which is the maximum packet size minus the 8 byte index trailer.
+    pub fn max_payload_size(&self) -> usize {
+        self.shared.max_packet_size.saturating_sub(8)
+    }
+
+    /// Checks if a payload of the given length would be accepted, without allocating it first.
+    pub fn check_payload_size(&self, len: usize) -> Result<(), CakapError> {
+        let max_size = self.max_payload_size();
+        if len > max_size {
+            Err(CakapError::PayloadTooLarge {
+                actual_size: len,
+                max_size,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
     /// Sends the given bytes unreliably.
     ///
     /// The last 8 bytes of the given message will be overwritten with zeroes, so leave space for that.
     /// If the given bytes are shorter than 9, the bytes will be returned. This means packets cannot
-    /// have a zero-sized payload.
+    /// have a zero-sized payload. Payloads longer than [`PacketBuilder::max_payload_size`] are rejected.
     pub fn new_unreliable(&self, body: PacketBody) -> Result<UnreliablePacket, BuildPacketError> {
         if body.data.is_empty() {
             return Err(BuildPacketError::EmptyBuffer { buffer: body.data });
         }
-        if body.data.len() > self.shared.max_packet_size {
-            return Err(BuildPacketError::BufferTooLarge {
+        if let Err(error) = self.check_payload_size(body.data.len()) {
+            return Err(BuildPacketError::PayloadTooLarge {
                 buffer: body.data,
-                max_packet_size: self.shared.max_packet_size,
+                error,
             });
         }
 
@@ -104,7 +140,7 @@ impl PacketBuilder {
     ///
     /// The last 8 bytes of the given message will be overwritten with a reliable index, so leave space for that.
     /// If the given bytes are shorter than 9, the bytes will be returned. This means packets cannot
-    /// have a zero-sized payload.
+    /// have a zero-sized payload. Payloads longer than [`PacketBuilder::max_payload_size`] are rejected.
     ///
     /// # Safety
     /// Strictly speaking, unexpected behavior can occur if this method is called 2^63 - 1 times per struct due to overflow.
@@ -113,10 +149,10 @@ impl PacketBuilder {
         if body.data.is_empty() {
             return Err(BuildPacketError::EmptyBuffer { buffer: body.data });
         }
-        if body.data.len() > self.shared.max_packet_size {
-            return Err(BuildPacketError::BufferTooLarge {
+        if let Err(error) = self.check_payload_size(body.data.len()) {
+            return Err(BuildPacketError::PayloadTooLarge {
                 buffer: body.data,
-                max_packet_size: self.shared.max_packet_size,
+                error,
             });
         }
 
@@ -146,6 +182,20 @@ pub struct HotPacket<'a> {
     pub(crate) inner: HotPacketInner<'a>,
 }
 
+impl<'a> HotPacket<'a> {
+    /// Copies the data of this packet if it is borrowed from the state machine, so that it
+    /// no longer borrows the state machine.
+    pub fn into_owned(self) -> HotPacket<'static> {
+        HotPacket {
+            inner: match self.inner {
+                HotPacketInner::Borrowed(buf) => HotPacketInner::Owned(buf.into()),
+                HotPacketInner::Owned(buf) => HotPacketInner::Owned(buf),
+                HotPacketInner::Index(buf) => HotPacketInner::Index(buf),
+            },
+        }
+    }
+}
+
 impl<'a> Deref for HotPacket<'a> {
     type Target = [u8];
 
@@ -177,7 +227,7 @@ impl<'a> PartialEq for HotPacket<'a> {
 impl<'a> Eq for HotPacket<'a> {}
 
 impl<'a> Debug for HotPacket<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("HotPacket")
             .field("data", &self.deref())
             .finish()
@@ -0,0 +1,63 @@
+use core::time::Duration;
+
+use crate::time::{DefaultTimestamp, Timestamp};
+
+/// A token bucket that limits the rate at which a [`PeerStateMachine`](crate::PeerStateMachine)
+/// recommends sending packets.
+///
+/// Each packet consumes one token, and tokens are refilled at `fill_rate` tokens per second, up to
+/// `burst` tokens. The bucket starts full.
+#[derive(Debug, Clone)]
+pub struct RateLimiter<I = DefaultTimestamp> {
+    fill_rate: f64,
+    burst: usize,
+    tokens: f64,
+    last_refill: Option<I>,
+}
+
+impl<I: Timestamp> RateLimiter<I> {
+    /// Creates a new [`RateLimiter`] that allows `fill_rate` packets per second on average, and at most
+    /// `burst` packets at once.
+    ///
+    /// # Panics
+    /// Panics if `fill_rate` is not positive or `burst` is zero.
+    pub fn new(fill_rate: f64, burst: usize) -> Self {
+        assert!(fill_rate > 0.0, "fill_rate must be positive");
+        assert!(burst > 0, "burst must be at least 1");
+        Self {
+            fill_rate,
+            burst,
+            tokens: burst as f64,
+            last_refill: None,
+        }
+    }
+
+    pub fn get_fill_rate(&self) -> f64 {
+        self.fill_rate
+    }
+
+    pub fn get_burst(&self) -> usize {
+        self.burst
+    }
+
+    fn refill(&mut self, now: I) {
+        if let Some(last_refill) = self.last_refill {
+            let elapsed = now.duration_since_or_zero(last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.fill_rate).min(self.burst as f64);
+        }
+        self.last_refill = Some(now);
+    }
+
+    /// Consumes a token if one is available, otherwise returns the amount of time until one will be.
+    pub fn try_acquire(&mut self, now: I) -> Result<(), Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.fill_rate,
+            ))
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use core::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+/// A point in time that the [`PeerStateMachine`](crate::PeerStateMachine) can be polled with.
+///
+/// This is implemented for any type that behaves like [`std::time::Instant`], which allows the
+/// protocol to run on targets that have their own monotonic clock. For example, [`Duration`]
+/// implements this trait, so the time since boot can be used as a timestamp.
+pub trait Timestamp:
+    Copy + Ord + Add<Duration, Output = Self> + Sub<Self, Output = Duration>
+{
+    /// Returns the amount of time elapsed from `earlier` to `self`, or zero if `earlier` is later than `self`.
+    fn duration_since_or_zero(self, earlier: Self) -> Duration {
+        if self > earlier {
+            self - earlier
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+impl<T> Timestamp for T where T: Copy + Ord + Add<Duration, Output = T> + Sub<T, Output = Duration> {}
+
+/// The [`Timestamp`] used by default.
+#[cfg(feature = "std")]
+pub type DefaultTimestamp = std::time::Instant;
+
+/// The [`Timestamp`] used by default.
+///
+/// Without the `std` feature, there is no clock available, so the time since some fixed point
+/// (such as boot) is used instead.
+#[cfg(not(feature = "std"))]
+pub type DefaultTimestamp = Duration;
@@ -0,0 +1,181 @@
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::{
+    error::{BuildPacketError, CakapError},
+    packet::{Action, HotPacket, ReliableIndex},
+    time::{DefaultTimestamp, Timestamp},
+    Event, PeerStateMachine, RecommendedAction,
+};
+
+/// Converts messages to and from bytes for a [`TypedPeerStateMachine`].
+///
+/// `S` is the type of message sent to the peer, and `D` is the type of message received from the peer.
+pub trait Codec<S: ?Sized, D> {
+    type Error;
+
+    fn encode(&mut self, msg: &S) -> Vec<u8>;
+    fn decode(&mut self, bytes: &[u8]) -> Result<D, Self::Error>;
+}
+
+/// A [`Codec`] that uses [`bitcode`], reusing its buffer between messages.
+#[cfg(feature = "bitcode")]
+#[derive(Default)]
+pub struct BitcodeCodec {
+    buffer: bitcode::Buffer,
+}
+
+#[cfg(feature = "bitcode")]
+impl<S, D> Codec<S, D> for BitcodeCodec
+where
+    S: bitcode::Encode + ?Sized,
+    D: bitcode::DecodeOwned,
+{
+    type Error = bitcode::Error;
+
+    fn encode(&mut self, msg: &S) -> Vec<u8> {
+        self.buffer.encode(msg).to_vec()
+    }
+
+    fn decode(&mut self, bytes: &[u8]) -> Result<D, Self::Error> {
+        self.buffer.decode(bytes)
+    }
+}
+
+/// The equivalent of [`RecommendedAction`] for a [`TypedPeerStateMachine`], where received data
+/// has already been decoded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TypedRecommendedAction<'a, D, E, I = DefaultTimestamp> {
+    /// See [`RecommendedAction::WaitForData`].
+    WaitForData,
+    /// See [`RecommendedAction::WaitForDuration`].
+    WaitForDuration(I),
+    /// See [`RecommendedAction::HandleError`].
+    HandleError(CakapError),
+    /// Handle the given message from the peer, then poll the state machine again with `NoEvent`.
+    HandleMessage(D),
+    /// Handle `message` from the peer, and send `to_send` to the peer.
+    HandleMessageAndSend { message: D, to_send: [u8; 8] },
+    /// Unreliable data from the peer could not be decoded.
+    ///
+    /// Handle the given error (by logging or otherwise) and poll the state machine again with `NoEvent`.
+    DecodeError(E),
+    /// Reliable data from the peer could not be decoded. The state machine has already recorded it as
+    /// received, so `to_send` is the acknowledgement for it.
+    ///
+    /// Handle `error`, and send `to_send` to the peer if the message should not be retransmitted. Since the
+    /// peer will retransmit the same bytes, not sending the acknowledgement only helps if the codec
+    /// can decode it later.
+    DecodeErrorAndSend { error: E, to_send: [u8; 8] },
+    /// See [`RecommendedAction::SendData`].
+    SendData(HotPacket<'a>),
+    /// See [`RecommendedAction::ReliableExpired`].
+    ReliableExpired(ReliableIndex),
+    /// See [`RecommendedAction::DrainedReliable`].
+    DrainedReliable(Vec<ReliableIndex>),
+    /// See [`RecommendedAction::PeerTimedOut`].
+    PeerTimedOut,
+}
+
+/// The action to take after sending a reliable message, and the index of its packet.
+type ReliableSend<'a, D, E, I> = (TypedRecommendedAction<'a, D, E, I>, ReliableIndex);
+
+/// A wrapper around a [`PeerStateMachine`] that encodes outgoing messages and decodes incoming
+/// messages with a [`Codec`].
+pub struct TypedPeerStateMachine<S: ?Sized, D, C, I = DefaultTimestamp> {
+    inner: PeerStateMachine<I>,
+    codec: C,
+    _phantom: PhantomData<fn(&S) -> D>,
+}
+
+impl<S, D, C, I> TypedPeerStateMachine<S, D, C, I>
+where
+    S: ?Sized,
+    C: Codec<S, D>,
+    I: Timestamp,
+{
+    pub fn new(inner: PeerStateMachine<I>, codec: C) -> Self {
+        Self {
+            inner,
+            codec,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn get_inner(&self) -> &PeerStateMachine<I> {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut PeerStateMachine<I> {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> PeerStateMachine<I> {
+        self.inner
+    }
+
+    /// Encodes and sends the given message reliably, returning the action to take and the index of the packet.
+    pub fn send_reliable<'a>(
+        &'a mut self,
+        msg: &S,
+        now: I,
+    ) -> Result<ReliableSend<'a, D, C::Error, I>, BuildPacketError> {
+        let packet = self
+            .inner
+            .get_packet_builder()
+            .new_reliable(self.codec.encode(msg).into())?;
+        let index = packet.get_index();
+        Ok((
+            self.poll(Event::Action(Action::SendReliable(packet)), now),
+            index,
+        ))
+    }
+
+    /// Encodes and sends the given message unreliably, returning the action to take.
+    pub fn send_unreliable<'a>(
+        &'a mut self,
+        msg: &S,
+        now: I,
+    ) -> Result<TypedRecommendedAction<'a, D, C::Error, I>, BuildPacketError> {
+        let packet = self
+            .inner
+            .get_packet_builder()
+            .new_unreliable(self.codec.encode(msg).into())?;
+        Ok(self.poll(Event::Action(Action::SendUnreliable(packet)), now))
+    }
+
+    /// Digests the given [`Event`] like [`PeerStateMachine::poll`], decoding any received data.
+    pub fn poll<'a>(
+        &'a mut self,
+        event: Event<'_>,
+        now: I,
+    ) -> TypedRecommendedAction<'a, D, C::Error, I> {
+        match self.inner.poll(event, now) {
+            RecommendedAction::WaitForData => TypedRecommendedAction::WaitForData,
+            RecommendedAction::WaitForDuration(deadline) => {
+                TypedRecommendedAction::WaitForDuration(deadline)
+            }
+            RecommendedAction::HandleError(e) => TypedRecommendedAction::HandleError(e),
+            RecommendedAction::HandleData(received) => match self.codec.decode(received) {
+                Ok(message) => TypedRecommendedAction::HandleMessage(message),
+                Err(e) => TypedRecommendedAction::DecodeError(e),
+            },
+            RecommendedAction::HandleDataAndSend { received, to_send } => {
+                match self.codec.decode(received) {
+                    Ok(message) => {
+                        TypedRecommendedAction::HandleMessageAndSend { message, to_send }
+                    }
+                    Err(error) => TypedRecommendedAction::DecodeErrorAndSend { error, to_send },
+                }
+            }
+            RecommendedAction::SendData(hot_packet) => TypedRecommendedAction::SendData(hot_packet),
+            RecommendedAction::ReliableExpired(index) => {
+                TypedRecommendedAction::ReliableExpired(index)
+            }
+            RecommendedAction::DrainedReliable(indices) => {
+                TypedRecommendedAction::DrainedReliable(indices)
+            }
+            RecommendedAction::PeerTimedOut => TypedRecommendedAction::PeerTimedOut,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    io::ErrorKind,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use cakap2::{Event, PeerStateMachine, RecommendedAction};
+
+/// The fraction of outgoing packets that are dropped on purpose.
+const DROP_RATE: f64 = 0.15;
+/// The longest time a peer will block on its socket before checking if it is done.
+const MAX_READ_TIMEOUT: Duration = Duration::from_millis(20);
+/// A peer that runs longer than this is assumed to be stuck.
+const TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One end of a lossy loopback connection.
+struct Link {
+    socket: UdpSocket,
+    /// State of a xorshift generator used to decide which packets to drop.
+    rng: u64,
+}
+
+impl Link {
+    fn pair() -> (Self, Self) {
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        a.connect(b.local_addr().unwrap()).unwrap();
+        b.connect(a.local_addr().unwrap()).unwrap();
+        (
+            Self {
+                socket: a,
+                rng: 0x9E37_79B9_7F4A_7C15,
+            },
+            Self {
+                socket: b,
+                rng: 0xD1B5_4A32_D192_ED03,
+            },
+        )
+    }
+
+    fn is_lost(&mut self) -> bool {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng as f64 / u64::MAX as f64) < DROP_RATE
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        if !self.is_lost() {
+            // The peer may have already stopped listening
+            let _ = self.socket.send(data);
+        }
+    }
+}
+
+/// Carries out the given action, returning how long to wait if the state machine should not be
+/// polled again immediately.
+fn handle(
+    action: RecommendedAction,
+    link: &mut Link,
+    received: &mut Vec<Vec<u8>>,
+) -> Option<Option<Duration>> {
+    match action {
+        RecommendedAction::WaitForData => return Some(None),
+        RecommendedAction::WaitForDuration(deadline) => {
+            return Some(Some(deadline.saturating_duration_since(Instant::now())))
+        }
+        RecommendedAction::HandleError(e) => panic!("{e}"),
+        RecommendedAction::HandleData(data) => received.push(data.to_vec()),
+        RecommendedAction::HandleDataAndSend {
+            received: data,
+            to_send,
+        } => {
+            received.push(data.to_vec());
+            link.send(&to_send);
+        }
+        RecommendedAction::SendData(hot_packet) => link.send(&hot_packet),
+        RecommendedAction::ReliableExpired(_)
+        | RecommendedAction::DrainedReliable(_)
+        | RecommendedAction::PeerTimedOut => {}
+    }
+    None
+}
+
+/// Sends the given payloads reliably and runs the event loop of the state machine until `is_done`
+/// returns `true`, returning every payload received from the peer.
+///
+/// If `reconnect` is `true`, a reconnection message is sent before the payloads.
+fn run_peer(
+    link: &mut Link,
+    state_machine: &mut PeerStateMachine,
+    payloads: Vec<Vec<u8>>,
+    reconnect: bool,
+    is_done: impl Fn(&PeerStateMachine) -> bool,
+) -> Vec<Vec<u8>> {
+    let start = Instant::now();
+    let mut received = vec![];
+    let mut buf = [0u8; 2048];
+    let mut pending_len = None;
+
+    if reconnect {
+        let (action, _) = state_machine.send_reconnection_msg(Instant::now());
+        handle(action, link, &mut received);
+    }
+    let builder = state_machine.get_packet_builder();
+    for payload in payloads {
+        let packet = builder.new_reliable(payload.into()).unwrap();
+        let action = state_machine.poll(Event::Action(packet.into()), Instant::now());
+        handle(action, link, &mut received);
+    }
+
+    loop {
+        assert!(
+            start.elapsed() < TEST_TIMEOUT,
+            "Peer did not finish in time"
+        );
+        let event = match pending_len.take() {
+            Some(len) => Event::IncomingData(&buf[..len]),
+            None => Event::NoEvent,
+        };
+        let mut action = state_machine.poll(event, Instant::now());
+        let wait = loop {
+            if let Some(wait) = handle(action, link, &mut received) {
+                break wait;
+            }
+            action = state_machine.poll(Event::NoEvent, Instant::now());
+        };
+        if is_done(state_machine) {
+            break received;
+        }
+
+        let timeout = wait
+            .unwrap_or(MAX_READ_TIMEOUT)
+            .clamp(Duration::from_millis(1), MAX_READ_TIMEOUT);
+        link.socket.set_read_timeout(Some(timeout)).unwrap();
+        match link.socket.recv(&mut buf) {
+            Ok(len) => pending_len = Some(len),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::ConnectionRefused
+                ) => {}
+            Err(e) => panic!("{e}"),
+        }
+    }
+}
+
+fn new_state_machine() -> PeerStateMachine {
+    PeerStateMachine::new(Duration::from_millis(50), 256, 1400)
+}
+
+fn payloads(tag: u8, count: u32) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let mut payload = vec![tag];
+            payload.extend_from_slice(&i.to_be_bytes());
+            payload
+        })
+        .collect()
+}
+
+/// Runs a peer that only receives in a separate thread until the returned flag is set.
+fn spawn_receiver(mut link: Link) -> (Arc<AtomicBool>, thread::JoinHandle<Vec<Vec<u8>>>) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop2 = stop.clone();
+    let handle = thread::spawn(move || {
+        run_peer(&mut link, &mut new_state_machine(), vec![], false, |_| {
+            stop2.load(Ordering::Relaxed)
+        })
+    });
+    (stop, handle)
+}
+
+#[test]
+fn reliable_delivered_exactly_once() {
+    let (mut a, b) = Link::pair();
+    let (stop, receiver) = spawn_receiver(b);
+
+    let sent = payloads(0, 100);
+    let sender = {
+        let sent = sent.clone();
+        thread::spawn(move || {
+            let mut state_machine = new_state_machine();
+            run_peer(&mut a, &mut state_machine, sent, false, |state_machine| {
+                state_machine.pending_reliable_count() == 0
+            })
+        })
+    };
+
+    assert!(sender.join().unwrap().is_empty());
+    stop.store(true, Ordering::Relaxed);
+    let mut received = receiver.join().unwrap();
+    received.sort();
+    assert_eq!(received, sent);
+}
+
+#[test]
+fn reconnection_clears_received_set() {
+    let (mut a, b) = Link::pair();
+    let (stop, receiver) = spawn_receiver(b);
+
+    let sender = thread::spawn(move || {
+        let is_done =
+            |state_machine: &PeerStateMachine| state_machine.pending_reliable_count() == 0;
+        let mut state_machine = new_state_machine();
+        run_peer(&mut a, &mut state_machine, payloads(0, 20), false, is_done);
+
+        // A fresh state machine reuses the reliable indices of the old one, which the receiver
+        // would ignore as duplicates if it did not clear its received set.
+        let mut state_machine = new_state_machine();
+        run_peer(&mut a, &mut state_machine, vec![], true, is_done);
+        run_peer(&mut a, &mut state_machine, payloads(1, 20), false, is_done);
+    });
+
+    sender.join().unwrap();
+    stop.store(true, Ordering::Relaxed);
+    let mut received = receiver.join().unwrap();
+    received.sort();
+    let mut expected = payloads(0, 20);
+    expected.extend(payloads(1, 20));
+    assert_eq!(received, expected);
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use cakap2::{Event, PeerStateMachine, RecommendedAction};
+use proptest::prelude::*;
+
+#[derive(Debug, Clone)]
+enum Op {
+    SendReliable,
+    SendUnreliable,
+    Advance(u64),
+}
+
+fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        Just(Op::SendReliable),
+        Just(Op::SendUnreliable),
+        (0u64..300).prop_map(Op::Advance),
+    ]
+}
+
+struct Peer {
+    state_machine: PeerStateMachine,
+    outgoing: VecDeque<Vec<u8>>,
+    reliable_received: Vec<Vec<u8>>,
+    unreliable_received: Vec<Vec<u8>>,
+}
+
+impl Peer {
+    fn new() -> Self {
+        Self {
+            state_machine: PeerStateMachine::new(Duration::from_millis(100), 256, 1400),
+            outgoing: VecDeque::new(),
+            reliable_received: Vec::new(),
+            unreliable_received: Vec::new(),
+        }
+    }
+
+    /// Polls the state machine with the given event, then keeps polling it until it asks to wait,
+    /// collecting the packets it wants to send and the payloads it received.
+    fn poll(&mut self, event: Event, now: Instant) {
+        let mut action = self.state_machine.poll(event, now);
+        loop {
+            match action {
+                RecommendedAction::WaitForData | RecommendedAction::WaitForDuration(_) => break,
+                RecommendedAction::HandleError(_)
+                | RecommendedAction::ReliableExpired(_)
+                | RecommendedAction::DrainedReliable(_)
+                | RecommendedAction::PeerTimedOut => {}
+                RecommendedAction::HandleData(received) => {
+                    self.unreliable_received.push(received.to_vec());
+                }
+                RecommendedAction::HandleDataAndSend { received, to_send } => {
+                    self.reliable_received.push(received.to_vec());
+                    self.outgoing.push_back(to_send.to_vec());
+                }
+                RecommendedAction::SendData(hot_packet) => {
+                    self.outgoing.push_back(hot_packet.to_vec());
+                }
+            }
+            action = self.state_machine.poll(Event::NoEvent, now);
+        }
+    }
+}
+
+/// Delivers packets between the two peers until neither has anything left to send.
+fn exchange(a: &mut Peer, b: &mut Peer, now: Instant, mut is_lost: impl FnMut() -> bool) {
+    while !a.outgoing.is_empty() || !b.outgoing.is_empty() {
+        while let Some(packet) = a.outgoing.pop_front() {
+            if !is_lost() {
+                b.poll(Event::IncomingData(&packet), now);
+            }
+        }
+        while let Some(packet) = b.outgoing.pop_front() {
+            if !is_lost() {
+                a.poll(Event::IncomingData(&packet), now);
+            }
+        }
+    }
+}
+
+fn simulate(ops: &[Op], losses: &[bool]) {
+    let mut a = Peer::new();
+    let mut b = Peer::new();
+    let builder = a.state_machine.get_packet_builder();
+    let mut now = Instant::now();
+    let mut reliable_sent = 0u32;
+    let mut unreliable_sent = 0u32;
+    let mut loss_index = 0usize;
+
+    for op in ops {
+        match op {
+            Op::SendReliable => {
+                let mut payload = vec![1];
+                payload.extend_from_slice(&reliable_sent.to_be_bytes());
+                reliable_sent += 1;
+                let packet = builder.new_reliable(payload.into()).unwrap();
+                a.poll(Event::Action(packet.into()), now);
+            }
+            Op::SendUnreliable => {
+                let mut payload = vec![0];
+                payload.extend_from_slice(&unreliable_sent.to_be_bytes());
+                unreliable_sent += 1;
+                let packet = builder.new_unreliable(payload.into()).unwrap();
+                a.poll(Event::Action(packet.into()), now);
+            }
+            Op::Advance(millis) => {
+                now += Duration::from_millis(*millis);
+                a.poll(Event::NoEvent, now);
+                b.poll(Event::NoEvent, now);
+            }
+        }
+        exchange(&mut a, &mut b, now, || {
+            let lost = losses
+                .get(loss_index % losses.len().max(1))
+                .copied()
+                .unwrap_or(false);
+            loss_index += 1;
+            lost
+        });
+    }
+
+    // Let the network settle without any packet loss
+    for _ in 0..10 {
+        now += Duration::from_secs(1);
+        a.poll(Event::NoEvent, now);
+        exchange(&mut a, &mut b, now, || false);
+    }
+
+    assert_eq!(a.state_machine.pending_reliable_count(), 0);
+
+    let mut reliable_received = b.reliable_received.clone();
+    reliable_received.sort();
+    let expected: Vec<_> = (0..reliable_sent)
+        .map(|i| {
+            let mut payload = vec![1];
+            payload.extend_from_slice(&i.to_be_bytes());
+            payload
+        })
+        .collect();
+    assert_eq!(reliable_received, expected);
+
+    let mut unreliable_received = b.unreliable_received.clone();
+    unreliable_received.dedup();
+    assert_eq!(unreliable_received.len(), b.unreliable_received.len());
+    assert!(unreliable_received.len() <= unreliable_sent as usize);
+}
+
+proptest! {
+    #[test]
+    fn reliable_delivered_exactly_once(
+        ops in prop::collection::vec(op_strategy(), 0..64),
+        losses in prop::collection::vec(prop::bool::weighted(0.3), 1..32),
+    ) {
+        simulate(&ops, &losses);
+    }
+}
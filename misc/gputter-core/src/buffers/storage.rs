@@ -7,6 +7,7 @@ use crate::{get_device, GpuDevice};
 use crate::size::StaticSize;
 
 use std::marker::PhantomData;
+use std::ops::Range;
 
 use crate::types::GpuType;
 
@@ -376,6 +377,82 @@ where
     }
 }
 
+impl<T, HM, SM> StorageBuffer<[T], HM, SM>
+where
+    [T]: GpuType<Size = DynamicSize<T>>,
+    HM: HostStorageBufferMode<HOST_CAN_READ = true>,
+{
+    /// Reads only the elements in `range` into `into`, which must be the same length as `range`.
+    pub fn read_range(&self, range: Range<usize>, into: &mut [T]) {
+        read_slice_range(self.read_buffer.as_ref().unwrap(), self.size.0, range, into);
+    }
+}
+
+impl<T, const N: usize, HM, SM> StorageBuffer<[T; N], HM, SM>
+where
+    [T; N]: GpuType<Size = StaticSize<[T; N]>>,
+    [T]: GpuType,
+    HM: HostStorageBufferMode<HOST_CAN_READ = true>,
+{
+    /// Reads only the elements in `range` into `into`, which must be the same length as `range`.
+    pub fn read_range(&self, range: Range<usize>, into: &mut [T]) {
+        read_slice_range(self.read_buffer.as_ref().unwrap(), N, range, into);
+    }
+}
+
+/// Reads the elements in `range` of a read buffer holding `len` elements into `into`.
+fn read_slice_range<T>(read_buffer: &wgpu::Buffer, len: usize, range: Range<usize>, into: &mut [T])
+where
+    [T]: GpuType,
+{
+    assert!(
+        range.start <= range.end && range.end <= len,
+        "Range {range:?} is out of bounds for buffer of length {len}"
+    );
+    assert_eq!(
+        range.len(),
+        into.len(),
+        "Range length does not match output length"
+    );
+    if range.is_empty() {
+        return;
+    }
+    let stride = size_of::<T>() as u64;
+    let (mapped_range, elements) = aligned_read_range(range, stride, len as u64 * stride);
+    let mapped = read_buffer.slice(mapped_range).get_mapped_range();
+    into.from_bytes(&mapped[elements]);
+}
+
+/// Returns the byte range that must be mapped to read the elements in `range` of a slice with the
+/// given element `stride`, along with where those elements are within the mapped bytes.
+///
+/// wgpu requires mapped ranges to start on a multiple of [`wgpu::MAP_ALIGNMENT`] and to have a size
+/// that is a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`].
+///
+/// # Panics
+/// Panics if the aligned range would extend past `buffer_size`. This only happens when `buffer_size`
+/// is not a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`] (such as an odd length `[u8]`), and wgpu
+/// cannot map such buffers at all.
+fn aligned_read_range(
+    range: Range<usize>,
+    stride: u64,
+    buffer_size: u64,
+) -> (Range<u64>, Range<usize>) {
+    let start = range.start as u64 * stride;
+    let end = range.end as u64 * stride;
+    let aligned_start = start - start % wgpu::MAP_ALIGNMENT;
+    let aligned_end = end.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+    assert!(
+        aligned_end <= buffer_size,
+        "Buffer size ({buffer_size} bytes) must be a multiple of {} bytes to be read",
+        wgpu::COPY_BUFFER_ALIGNMENT
+    );
+    (
+        aligned_start..aligned_end,
+        (start - aligned_start) as usize..(end - aligned_start) as usize,
+    )
+}
+
 impl<T, HM, SM> StorageBuffer<T, HM, SM>
 where
     T: GpuType<Size = StaticSize<T>>,
@@ -416,3 +493,50 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::aligned_read_range;
+
+    #[test]
+    fn aligned_read_range_1() {
+        // Already aligned
+        assert_eq!(aligned_read_range(0..3, 4, 40), (0..12, 0..12));
+        // Unaligned start
+        assert_eq!(aligned_read_range(3..5, 4, 40), (8..20, 4..12));
+        // Unaligned start and end with single byte elements
+        assert_eq!(aligned_read_range(5..7, 1, 16), (0..8, 5..7));
+        assert_eq!(aligned_read_range(9..10, 1, 16), (8..12, 1..2));
+        // Two byte elements
+        assert_eq!(aligned_read_range(3..4, 2, 8), (0..8, 6..8));
+        assert_eq!(aligned_read_range(1..2, 2, 8), (0..4, 2..4));
+        // Elements larger than the alignment
+        assert_eq!(aligned_read_range(1..2, 12, 48), (8..24, 4..16));
+        assert_eq!(aligned_read_range(2..4, 16, 64), (32..64, 0..32));
+    }
+
+    #[test]
+    fn aligned_read_range_2() {
+        for stride in [1, 2, 3, 4, 6, 8, 12, 16] {
+            let len = 24usize;
+            let buffer_size = (len as u64 * stride).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+            for start in 0..len {
+                for end in start + 1..=len {
+                    let (mapped, elements) = aligned_read_range(start..end, stride, buffer_size);
+                    assert_eq!(mapped.start % wgpu::MAP_ALIGNMENT, 0);
+                    assert_eq!((mapped.end - mapped.start) % wgpu::COPY_BUFFER_ALIGNMENT, 0);
+                    assert!(mapped.end <= buffer_size);
+                    assert_eq!(mapped.start + elements.start as u64, start as u64 * stride);
+                    assert_eq!(elements.len() as u64, (end - start) as u64 * stride);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn aligned_read_range_unaligned_buffer() {
+        // An odd length `[u8]` cannot be mapped up to its last byte
+        aligned_read_range(0..3, 1, 3);
+    }
+}
@@ -116,8 +116,42 @@ fn type_resolver(s: &str, uint_consts: &FxHashMap<&str, Option<u32>>) -> String
     s.into()
 }
 
-// Eventually check errors like these
-// Shader entry point's workgroup size [36, 24, 1] (864 total invocations) must be less or equal to the per-dimension limit [256, 256, 64] and the total invocation limit 256
+/// The default per-dimension workgroup size limit of wgpu
+const MAX_WORKGROUP_SIZE: [u32; 3] = [256, 256, 64];
+/// The default total invocations per workgroup limit of wgpu
+const MAX_WORKGROUP_INVOCATIONS: u32 = 256;
+
+/// Checks that every `@workgroup_size` made of integer literals is within wgpu's default limits.
+///
+/// Sizes that use constants are skipped as their values may not be known yet.
+fn check_workgroup_sizes(shader: &str) -> Result<(), String> {
+    let re = Regex::new(r"@workgroup_size\s*\(([^\)]*)\)").unwrap();
+    for caps in re.captures_iter(shader) {
+        let (_, [args]) = caps.extract();
+        let Ok(dims) = args
+            .split(',')
+            .map(str::trim)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| u32::from_str(arg.trim_end_matches('u')))
+            .collect::<Result<Vec<_>, _>>()
+        else {
+            continue;
+        };
+        let mut size = [1u32; 3];
+        for (dim, value) in size.iter_mut().zip(dims) {
+            *dim = value;
+        }
+        let total = size.iter().map(|&n| n as u64).product::<u64>();
+        if size.iter().zip(MAX_WORKGROUP_SIZE).any(|(&n, max)| n > max)
+            || total > MAX_WORKGROUP_INVOCATIONS as u64
+        {
+            return Err(format!(
+                "Workgroup size {size:?} ({total} total invocations) must be less or equal to the per-dimension limit {MAX_WORKGROUP_SIZE:?} and the total invocation limit {MAX_WORKGROUP_INVOCATIONS}"
+            ));
+        }
+    }
+    Ok(())
+}
 
 #[proc_macro]
 pub fn build_shader(input: TokenStream) -> TokenStream {
@@ -125,6 +159,7 @@ pub fn build_shader(input: TokenStream) -> TokenStream {
     let BuildShader {
         vis, name, shader, ..
     } = parse_macro_input!(input as BuildShader);
+    let shader_span = shader.span();
 
     // remove comments
     let re = Regex::new(r"//[[[:blank:]]\S]*\n").unwrap();
@@ -142,6 +177,10 @@ pub fn build_shader(input: TokenStream) -> TokenStream {
         tmp
     };
 
+    if let Err(e) = check_workgroup_sizes(&shader) {
+        return syn::Error::new(shader_span, e).into_compile_error().into();
+    }
+
     // Find all compute functions
     let re = Regex::new(r"@compute[\s@a-zA-Z0-9\(\)_,\*\+\-/%]+fn\s+([a-zA-Z0-9]+)\s*\(").unwrap();
     let compute_fns: Vec<_> = re
@@ -337,10 +376,12 @@ pub fn build_shader(input: TokenStream) -> TokenStream {
         });
     })) {
         let payload: Box<String> = panic.downcast().unwrap();
-        panic!(
-            "Failed to compile the following mock shader:\n\n{}\n\n{}",
-            tmp_shader, payload
-        );
+        return syn::Error::new(
+            shader_span,
+            format!("WGSL compilation failed: {payload}\n\nMock shader:\n\n{tmp_shader}"),
+        )
+        .into_compile_error()
+        .into();
     }
 
     // Replace substitutions with variable names
@@ -445,3 +486,46 @@ pub fn build_shader(input: TokenStream) -> TokenStream {
     // Hand the output tokens back to the compiler
     TokenStream::from(expanded)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::check_workgroup_sizes;
+
+    #[test]
+    fn workgroup_sizes_within_limits() {
+        assert!(check_workgroup_sizes("@compute @workgroup_size(64) fn main() {}").is_ok());
+        assert!(check_workgroup_sizes("@compute @workgroup_size(16, 16) fn main() {}").is_ok());
+        assert!(check_workgroup_sizes("@compute @workgroup_size(4, 4, 16) fn main() {}").is_ok());
+        assert!(check_workgroup_sizes("@compute @workgroup_size(256, 1, 1) fn main() {}").is_ok());
+    }
+
+    #[test]
+    fn workgroup_sizes_over_total() {
+        assert!(check_workgroup_sizes("@compute @workgroup_size(16, 32) fn main() {}").is_err());
+        assert!(check_workgroup_sizes("@compute @workgroup_size(257) fn main() {}").is_err());
+    }
+
+    #[test]
+    fn workgroup_sizes_over_dimension() {
+        assert!(check_workgroup_sizes("@compute @workgroup_size(1, 1, 65) fn main() {}").is_err());
+    }
+
+    #[test]
+    fn workgroup_sizes_with_suffix() {
+        assert!(check_workgroup_sizes("@compute @workgroup_size(8u, 8u) fn main() {}").is_ok());
+        assert!(check_workgroup_sizes("@compute @workgroup_size(16u, 32u) fn main() {}").is_err());
+    }
+
+    #[test]
+    fn workgroup_sizes_with_constants() {
+        assert!(check_workgroup_sizes("@compute @workgroup_size(WIDTH, 512) fn main() {}").is_ok());
+        assert!(check_workgroup_sizes("@compute @workgroup_size({{size}}) fn main() {}").is_ok());
+    }
+
+    #[test]
+    fn workgroup_sizes_checks_every_function() {
+        let shader =
+            "@compute @workgroup_size(8) fn a() {}\n@compute @workgroup_size(1024) fn b() {}";
+        assert!(check_workgroup_sizes(shader).is_err());
+    }
+}
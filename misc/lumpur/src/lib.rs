@@ -1,36 +1,31 @@
 #![feature(os_string_pathbuf_leak)]
 
 use std::any::type_name;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::env::VarError;
 use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::net::{TcpListener, TcpStream};
 use std::panic::set_hook;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use chrono::{Datelike, Timelike};
 use config::Configuration;
-use cursive::event::{Event, EventResult, Key};
-use cursive::theme::{Color, ColorStyle, ColorType, Effect, Style, Theme};
-use cursive::utils::markup::StyledString;
-use cursive::view::{Nameable, Resizable, ScrollStrategy, Scrollable};
-use cursive::views::{
-    Button, HideableView, Layer, LinearLayout, NamedView, ScrollView, TextView, ThemedView,
-};
-use cursive::Cursive;
 use parking_lot::Mutex;
-use raw_sync::events::{EventInit, EventState};
-use raw_sync::Timeout;
-use shared_memory::{ShmemConf, ShmemError};
 use tracing::Level;
 use tracing_subscriber::fmt::time::Uptime;
 
 pub mod config;
+#[cfg(feature = "tui")]
+mod tui;
 
-#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+#[allow(clippy::upper_case_acronyms)]
 enum SerdeLevel {
     ERROR,
     WARN,
@@ -51,14 +46,44 @@ impl From<SerdeLevel> for Level {
     }
 }
 
-enum LogMessage {
+impl From<Level> for SerdeLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::ERROR => SerdeLevel::ERROR,
+            Level::WARN => SerdeLevel::WARN,
+            Level::INFO => SerdeLevel::INFO,
+            Level::DEBUG => SerdeLevel::DEBUG,
+            _ => SerdeLevel::TRACE,
+        }
+    }
+}
+
+mod serde_level {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use tracing::Level;
+
+    use crate::SerdeLevel;
+
+    pub fn serialize<S: Serializer>(level: &Level, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeLevel::from(*level).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Level, D::Error> {
+        SerdeLevel::deserialize(deserializer).map(Into::into)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub enum LogMessage {
     Stdio {
+        #[serde(with = "serde_level")]
         level: Level,
         stdio: String,
         message: String,
     },
     Standard {
         timestamp: f32,
+        #[serde(with = "serde_level")]
         level: Level,
         thread_name: String,
         target: String,
@@ -68,6 +93,7 @@ enum LogMessage {
     },
 }
 
+#[cfg(feature = "tui")]
 impl LogMessage {
     fn aggregate(&self) -> String {
         match self {
@@ -104,12 +130,12 @@ struct RawLogMessage {
 const EMBEDDED_KEY: &str = "__LUMPUR_EMBEDDED";
 const EMBEDDED_VAL: &str = "1";
 const SHMEM_VAR_KEY: &str = "__LUMPUR_SHMEM_FLINK";
-const LOG_VIEW: &str = "log_view";
-const LOG_SCROLL_VIEW: &str = "log_scroll_view";
+#[cfg(feature = "tui")]
+const DEFAULT_MAX_LINES: usize = 1000;
 
-static ON_EXIT: Mutex<Option<Box<dyn FnOnce() -> () + Send>>> = Mutex::new(None);
+static ON_EXIT: Mutex<Option<Box<dyn FnOnce() + Send>>> = Mutex::new(None);
 
-pub fn set_on_exit(f: impl FnOnce() -> () + Send + 'static) {
+pub fn set_on_exit(f: impl FnOnce() + Send + 'static) {
     *ON_EXIT.lock() = Some(Box::new(f));
 }
 
@@ -133,36 +159,29 @@ fn subprocess_fn<C: Configuration>() -> C {
         tracing::error!("{info}");
     }));
 
-    let flink = std::env::var(SHMEM_VAR_KEY).unwrap();
+    #[cfg(feature = "tui")]
+    if let Ok(flink) = std::env::var(SHMEM_VAR_KEY) {
+        std::thread::spawn(move || tui::wait_for_ctrlc(&flink));
+        return read_config();
+    }
 
-    // Ctrl-C listener
-    std::thread::spawn(move || {
-        let shmem = match ShmemConf::new().size(4096).flink(&flink).open() {
-            Ok(shmem) => shmem,
-            Err(e) => {
-                tracing::error!("Failed to open shared memory segment: {e}");
-                return;
-            }
-        };
-        let result = unsafe { raw_sync::events::Event::from_existing(shmem.as_ptr()) };
-        let (evt, _used_bytes) = match result {
-            Ok(evt) => evt,
-            Err(e) => {
-                tracing::error!("Failed to create ctrl-c event listener: {e}");
-                return;
-            }
-        };
-        if let Err(e) = evt.wait(Timeout::Infinite) {
-            tracing::error!("Failed to wait for ctrl-c event: {e}");
-        }
-        tracing::warn!("Ctrl-C event received. Exiting...");
-        if let Some(f) = ON_EXIT.lock().take() {
-            f();
-        } else {
-            std::process::exit(0);
-        }
-    });
+    // Lumpur is headless, so Ctrl-C is delivered to this process directly
+    if let Err(e) = ctrlc::set_handler(on_ctrlc) {
+        tracing::error!("Failed to set ctrl-c handler: {e}");
+    }
+    read_config()
+}
+
+fn on_ctrlc() {
+    tracing::warn!("Ctrl-C event received. Exiting...");
+    if let Some(f) = ON_EXIT.lock().take() {
+        f();
+    } else {
+        std::process::exit(0);
+    }
+}
 
+fn read_config<C: Configuration>() -> C {
     if !Path::new("app-config.toml").exists() {
         tracing::error!("app-config.toml not found");
         std::process::exit(1);
@@ -229,7 +248,7 @@ fn make_line_f(
                     .canonicalize()
                     .unwrap_or_else(|_| PathBuf::from(&log.filename));
                 filename
-                    .strip_prefix(&current_dir)
+                    .strip_prefix(current_dir)
                     .unwrap_or(&filename)
                     .to_string_lossy()
                     .into_owned()
@@ -245,49 +264,161 @@ fn make_line_f(
     }
 }
 
-fn log_write_thread(write_rx: Receiver<Arc<LogMessage>>, mut log_file: LineWriter<std::fs::File>) {
+/// Number of log lines replayed to clients that connect to the log server.
+const LOG_SERVER_BACKLOG: usize = 1000;
+
+#[derive(Default)]
+struct LogServer {
+    backlog: VecDeque<Arc<str>>,
+    /// The number of lines ever broadcast, including those no longer in the backlog.
+    total: usize,
+    clients: Vec<TcpStream>,
+}
+
+impl LogServer {
+    fn broadcast(&mut self, msg: &LogMessage) {
+        let Ok(line) = serde_json::to_string(msg) else {
+            return;
+        };
+        if self.backlog.len() >= LOG_SERVER_BACKLOG {
+            self.backlog.pop_front();
+        }
+        self.total += 1;
+        self.clients
+            .retain_mut(|client| writeln!(client, "{line}").is_ok());
+        self.backlog.push_back(line.into());
+    }
+}
+
+fn log_server_thread(listener: TcpListener, server: Arc<Mutex<LogServer>>) {
+    for stream in listener.incoming() {
+        let Ok(mut stream) = stream else {
+            continue;
+        };
+        // A stalled client should not hold up the log file
+        if stream
+            .set_write_timeout(Some(Duration::from_secs(1)))
+            .is_err()
+        {
+            continue;
+        }
+        // Replay a snapshot of the backlog without holding the lock, so that a slow client does
+        // not stall logging
+        let (backlog, total): (Vec<_>, _) = {
+            let server = server.lock();
+            (server.backlog.iter().cloned().collect(), server.total)
+        };
+        if !backlog
+            .iter()
+            .all(|line| writeln!(stream, "{line}").is_ok())
+        {
+            continue;
+        }
+        // Catch up on the lines logged while replaying, which is usually none
+        let mut server = server.lock();
+        let missed = (server.total - total).min(server.backlog.len());
+        let start = server.backlog.len() - missed;
+        if server
+            .backlog
+            .range(start..)
+            .all(|line| writeln!(stream, "{line}").is_ok())
+        {
+            server.clients.push(stream);
+        }
+    }
+}
+
+fn format_log_line(msg: &LogMessage) -> String {
+    match msg {
+        LogMessage::Stdio {
+            level,
+            stdio,
+            message,
+        } => {
+            format!("[         {level: <5} {stdio}] {message}")
+        }
+        LogMessage::Standard {
+            timestamp,
+            level,
+            thread_name,
+            target,
+            filename,
+            line_number,
+            fields,
+        } => {
+            let mut message = fields
+                .get("message")
+                .map(|v| {
+                    if let Some(msg) = v.as_str() {
+                        msg.replace('\n', "\n    ")
+                    } else {
+                        v.to_string()
+                    }
+                })
+                .unwrap_or_default();
+
+            if message.is_empty() || fields.len() > 1 {
+                message += "    {";
+                for (k, v) in fields {
+                    if k == "message" {
+                        continue;
+                    }
+                    message += &format!(" {k}: {v},");
+                }
+                message += " }";
+            }
+            format!("[{timestamp: >7.2}s {level: <5} {target: <10} {thread_name: <12} {filename}:{line_number}] {message}")
+        }
+    }
+}
+
+impl std::fmt::Display for LogMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&format_log_line(self))
+    }
+}
+
+/// How messages are written to `app.log`.
+#[derive(Default, Clone, Copy, Debug)]
+pub enum LogFormat {
+    /// One human readable line per message.
+    #[default]
+    Text,
+    /// Consecutive MessagePack encoded [`LogMessage`]s, which can be read with [`read_log_binary`].
+    MessagePack,
+}
+
+/// Reads a log file that was written with [`LogFormat::MessagePack`].
+///
+/// Iteration stops at the end of the file, or at the first message that cannot be decoded.
+pub fn read_log_binary(path: &Path) -> std::io::Result<impl Iterator<Item = LogMessage>> {
+    let file = std::fs::File::open(path)?;
+    let mut deserializer = rmp_serde::Deserializer::new(BufReader::new(file));
+    Ok(std::iter::from_fn(move || {
+        <LogMessage as serde::Deserialize>::deserialize(&mut deserializer).ok()
+    }))
+}
+
+fn log_write_thread(
+    write_rx: Receiver<Arc<LogMessage>>,
+    mut log_file: LineWriter<std::fs::File>,
+    log_format: LogFormat,
+    log_server: Option<Arc<Mutex<LogServer>>>,
+) {
     while let Ok(msg) = write_rx.recv() {
-        match &*msg {
-            LogMessage::Stdio {
-                level,
-                stdio,
-                message,
-            } => {
-                let _ = writeln!(log_file, "[         {level: <5} {stdio}] {message}");
+        match log_format {
+            LogFormat::Text => {
+                let _ = writeln!(log_file, "{msg}");
             }
-            LogMessage::Standard {
-                timestamp,
-                level,
-                thread_name,
-                target,
-                filename,
-                line_number,
-                fields,
-            } => {
-                let mut message = fields
-                    .get("message")
-                    .map(|v| {
-                        if let Some(msg) = v.as_str() {
-                            msg.replace('\n', "\n    ")
-                        } else {
-                            v.to_string()
-                        }
-                    })
-                    .unwrap_or_default();
-
-                if message.is_empty() || fields.len() > 1 {
-                    message += "    {";
-                    for (k, v) in fields {
-                        if k == "message" {
-                            continue;
-                        }
-                        message += &format!(" {k}: {v},");
-                    }
-                    message += " }";
+            LogFormat::MessagePack => {
+                if rmp_serde::encode::write(&mut log_file, &*msg).is_ok() {
+                    let _ = log_file.flush();
                 }
-                let _ = writeln!(log_file, "[{timestamp: >7.2}s {level: <5} {target: <10} {thread_name: <12} {filename}:{line_number}] {message}");
             }
         }
+        if let Some(log_server) = &log_server {
+            log_server.lock().broadcast(&msg);
+        }
     }
     let _ = log_file.flush();
 }
@@ -326,15 +457,84 @@ impl NewWorkingDirectory {
     }
 }
 
+struct HealthCheck {
+    name: String,
+    interval: Duration,
+    check: Box<dyn Fn() -> bool + Send>,
+}
+
 pub enum PathReference {
     Copy(PathBuf),
     Symlink(PathBuf),
 }
 
+/// How long the child must run before its previous restarts are forgotten.
+const RESTART_RESET_UPTIME: Duration = Duration::from_secs(60);
+/// The longest delay between restarts, unless the policy's own `delay` is longer.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// What to do when the child process exits by itself.
+///
+/// `max_retries` limits the number of consecutive restarts. Once the child has been running for
+/// a minute, the count starts over. The child is never restarted after Ctrl-C has been pressed.
+#[derive(Default, Clone, Copy, Debug)]
+pub enum RestartPolicy {
+    /// Leave the child stopped.
+    #[default]
+    Never,
+    /// Restart the child however it exited, waiting `delay` before the first restart and doubling
+    /// the wait for each consecutive restart, up to a minute.
+    Always { max_retries: usize, delay: Duration },
+    /// Restart the child only if it exited unsuccessfully, waiting like [`RestartPolicy::Always`].
+    OnFailure { max_retries: usize, delay: Duration },
+}
+
+impl RestartPolicy {
+    /// Returns how long to wait before restarting a child that exited with `status` after already
+    /// being restarted `restarts` times in a row, or `None` if it should not be restarted.
+    fn restart_delay(self, status: ExitStatus, restarts: usize) -> Option<Duration> {
+        let (max_retries, delay) = match self {
+            RestartPolicy::Never => return None,
+            RestartPolicy::Always { max_retries, delay } => (max_retries, delay),
+            RestartPolicy::OnFailure { max_retries, delay } => {
+                if status.success() {
+                    return None;
+                }
+                (max_retries, delay)
+            }
+        };
+        if restarts >= max_retries {
+            return None;
+        }
+        let backoff = delay.saturating_mul(1 << restarts.min(16));
+        Some(backoff.min(MAX_RESTART_DELAY.max(delay)))
+    }
+
+    fn restart_message(self, status: ExitStatus, attempt: usize) -> String {
+        let max_retries = match self {
+            RestartPolicy::Never => 0,
+            RestartPolicy::Always { max_retries, .. }
+            | RestartPolicy::OnFailure { max_retries, .. } => max_retries,
+        };
+        let last_exit = status
+            .code()
+            .map(|code| code.to_string())
+            .unwrap_or_else(|| status.to_string());
+        format!("       [RESTARTING (attempt {attempt}/{max_retries}, last exit: {last_exit})]")
+    }
+}
+
 pub struct LumpurBuilder {
     pub new_working_directory: NewWorkingDirectory,
     pub path_reference: Vec<PathReference>,
     pub default_commands: bool,
+    pub log_server_port: Option<u16>,
+    pub headless: bool,
+    pub restart_policy: RestartPolicy,
+    pub session_metadata: Option<BTreeMap<String, String>>,
+    pub log_format: LogFormat,
+    pub max_lines: Option<usize>,
+    health_checks: Vec<HealthCheck>,
 }
 
 impl Default for LumpurBuilder {
@@ -349,6 +549,13 @@ impl LumpurBuilder {
             new_working_directory: NewWorkingDirectory::default(),
             path_reference: vec![PathReference::Copy(PathBuf::from("app-config.toml"))],
             default_commands: true,
+            log_server_port: None,
+            headless: false,
+            restart_policy: RestartPolicy::default(),
+            session_metadata: None,
+            log_format: LogFormat::default(),
+            max_lines: None,
+            health_checks: Vec::new(),
         }
     }
 
@@ -374,6 +581,76 @@ impl LumpurBuilder {
         self
     }
 
+    /// Writes `session.toml` into the working directory on startup, containing the start time,
+    /// hostname, pid and working directory of the session, with `meta` under a `[metadata]` table.
+    ///
+    /// `git_commit` is also filled in from the `GIT_COMMIT` environment variable if it is set.
+    /// Keeping `meta` in its own table means it can use any key without replacing these.
+    pub fn set_session_metadata(mut self, meta: BTreeMap<String, String>) -> Self {
+        self.session_metadata = Some(meta);
+        self
+    }
+
+    /// Sets how messages are written to `app.log`.
+    ///
+    /// The TUI and the log server always show text.
+    pub fn log_format(mut self, log_format: LogFormat) -> Self {
+        self.log_format = log_format;
+        self
+    }
+
+    /// Sets how many log lines the TUI keeps before dropping the oldest ones. Defaults to 1000.
+    ///
+    /// If this is not set, the deprecated `MAX_LINES` environment variable is still read instead.
+    pub fn max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Streams every message written to `app.log` to clients that connect to `port` over TCP, as
+    /// one JSON encoded [`LogMessage`] per line.
+    ///
+    /// Clients first receive the most recent messages, so something as simple as
+    /// `nc <robot-ip> <port>` can be used to follow the logs remotely.
+    pub fn enable_log_server(mut self, port: u16) -> Self {
+        self.log_server_port = Some(port);
+        self
+    }
+
+    /// Skips the TUI and writes log lines to stdout instead, for when there is no terminal (CI,
+    /// systemd, etc).
+    ///
+    /// The first Ctrl-C (or SIGTERM) is left for the child to handle, and the second kills it.
+    ///
+    /// Without the default `tui` feature, lumpur is always headless and this does nothing.
+    pub fn headless(mut self, enabled: bool) -> Self {
+        self.headless = enabled;
+        self
+    }
+
+    /// Sets whether the child process is restarted when it exits.
+    pub fn restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Runs `check` every `interval` on a background thread, showing its latest result in the header.
+    ///
+    /// A warning is logged whenever the check starts failing.
+    pub fn add_health_check(
+        mut self,
+        name: impl Into<String>,
+        interval: Duration,
+        check: impl Fn() -> bool + Send + 'static,
+    ) -> Self {
+        self.health_checks.push(HealthCheck {
+            name: name.into(),
+            interval,
+            check: Box::new(check),
+        });
+        self
+    }
+
     fn process_default_commands(&self) {
         let Some(cmd_name) = std::env::args().nth(1) else {
             return;
@@ -487,80 +764,213 @@ impl LumpurBuilder {
             std::env::set_current_dir(new_current_dir).expect("Failed to set current directory");
         }
 
-        let mut shmem = None;
-        let mut flink = String::new();
-        for i in 0..1024 {
-            flink = format!(".lumpur-{i}.shmem");
-            match ShmemConf::new().size(4096).flink(&flink).create() {
-                Ok(m) => {
-                    shmem = Some(m);
-                    break;
-                }
-                Err(ShmemError::LinkExists) => {}
-                Err(e) => {
-                    panic!("Failed to create shared memory segment: {e}");
-                }
-            };
+        // The headless child receives Ctrl-C directly instead of through shared memory
+        #[cfg(feature = "tui")]
+        let ctrlc_shmem = (!self.headless).then(tui::create_ctrlc_event);
+        #[cfg(feature = "tui")]
+        let flink = ctrlc_shmem.as_ref().map(|(_, _, flink)| flink.clone());
+        #[cfg(not(feature = "tui"))]
+        let flink = None;
+
+        if let Some(meta) = self.session_metadata {
+            write_session_metadata(meta);
         }
-        let Some(shmem) = shmem else {
-            panic!("Failed to create shared memory segment. All slots occupied");
-        };
-        let (ctrlc_evt, _used_bytes) = unsafe {
-            raw_sync::events::Event::new(shmem.as_ptr(), true)
-                .expect("Failed to create ctrl-c event")
-        };
 
         let log_file =
             std::fs::File::create("app.log").expect("Failed to create log file (app.log)");
         let mut log_file = LineWriter::new(log_file);
-        writeln!(
-            log_file,
-            "!Program started with pid: {}",
-            std::process::id()
-        )
-        .expect("Failed to write to log file (app.log)");
+        let mut header = vec![format!("Program started with pid: {}", std::process::id())];
         if std::env::args().len() > 1 {
-            write!(log_file, "!Arguments:").expect("Failed to write to log file (app.log)");
+            let mut arguments = "Arguments:".to_string();
             for arg in std::env::args().skip(1) {
-                write!(log_file, " {}", arg).expect("Failed to write to log file (app.log)");
+                arguments += &format!(" {arg}");
             }
-            writeln!(log_file).expect("Failed to write to log file (app.log)");
+            header.push(arguments);
         } else {
-            writeln!(log_file, "!No arguments provided")
-                .expect("Failed to write to log file (app.log)");
+            header.push("No arguments provided".into());
         }
+        if let LogFormat::Text = self.log_format {
+            for line in header.drain(..) {
+                writeln!(log_file, "!{line}").expect("Failed to write to log file (app.log)");
+            }
+        }
+        let log_server = self.log_server_port.map(|port| {
+            let listener = TcpListener::bind(("0.0.0.0", port)).expect("Failed to bind log server");
+            let log_server = Arc::new(Mutex::new(LogServer::default()));
+            let log_server2 = log_server.clone();
+            std::thread::spawn(move || log_server_thread(listener, log_server2));
+            log_server
+        });
         let (write_tx, write_rx) = std::sync::mpsc::channel::<Arc<LogMessage>>();
-        let write_thr = std::thread::spawn(move || log_write_thread(write_rx, log_file));
+        let log_format = self.log_format;
+        let write_thr = std::thread::spawn(move || {
+            log_write_thread(write_rx, log_file, log_format, log_server)
+        });
+        // Binary logs cannot hold the text header, so it is logged as regular messages instead
+        for message in header {
+            let _ = write_tx.send(Arc::new(LogMessage::Stdio {
+                level: Level::INFO,
+                stdio: "lumpur".into(),
+                message,
+            }));
+        }
 
-        let max_lines: usize = std::env::var("MAX_LINES")
-            .map(|s| s.parse().unwrap_or(1000))
-            .unwrap_or(1000);
-        let mut child = Command::new(std::env::current_exe().expect("Failed to get current exe"))
-            .env(EMBEDDED_KEY, EMBEDDED_VAL)
-            .env(SHMEM_VAR_KEY, flink)
-            .args(std::env::args().skip(1))
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .expect("Failed to spawn child process");
         let (log_tx, log_rx) = std::sync::mpsc::channel::<Arc<LogMessage>>();
-        let stdout = BufReader::new(child.stdout.take().unwrap());
-        let stderr = BufReader::new(child.stderr.take().unwrap());
         let current_dir: &_ = std::env::current_dir()
             .expect("Failed to get current dir")
             .canonicalize()
             .expect("Failed to canonicalize current dir")
             .leak();
+        let spawner = ChildSpawner {
+            flink,
+            log_tx: log_tx.clone(),
+            write_tx: write_tx.clone(),
+            current_dir,
+            child_stdin: Box::leak(Box::new(Mutex::new(None))),
+        };
+        let (child, reader_thrs) = spawner.spawn().expect("Failed to spawn child process");
+        #[cfg_attr(not(feature = "tui"), allow(unused_variables))]
+        let health_checks: Vec<(String, &AtomicBool)> = self
+            .health_checks
+            .into_iter()
+            .map(
+                |HealthCheck {
+                     name,
+                     interval,
+                     check,
+                 }| {
+                    let healthy: &_ = Box::leak(Box::new(AtomicBool::new(true)));
+                    let log_tx = log_tx.clone();
+                    let write_tx = write_tx.clone();
+                    let message = format!("Health check failed: {name}");
+                    std::thread::spawn(move || loop {
+                        let is_healthy = check();
+                        if !is_healthy && healthy.load(Ordering::Relaxed) {
+                            let log = Arc::new(LogMessage::Stdio {
+                                level: Level::WARN,
+                                stdio: "health".into(),
+                                message: message.clone(),
+                            });
+                            let _ = log_tx.send(log.clone());
+                            let _ = write_tx.send(log);
+                        }
+                        healthy.store(is_healthy, Ordering::Relaxed);
+                        std::thread::sleep(interval);
+                    });
+                    (name, healthy)
+                },
+            )
+            .collect();
+
+        #[cfg(feature = "tui")]
+        if let Some((shmem, ctrlc_evt, _)) = ctrlc_shmem {
+            let max_lines: usize = self.max_lines.unwrap_or_else(|| {
+                std::env::var("MAX_LINES")
+                    .map(|s| s.parse().unwrap_or(DEFAULT_MAX_LINES))
+                    .unwrap_or(DEFAULT_MAX_LINES)
+            });
+            let exit_code = tui::run_tui(
+                &spawner,
+                child,
+                log_rx,
+                health_checks,
+                max_lines,
+                self.restart_policy,
+                &*ctrlc_evt,
+            );
+            // Drop these to remove the shared memory segment file
+            drop(ctrlc_evt);
+            drop(shmem);
+            let _ = write_thr.join();
+            std::process::exit(exit_code);
+        }
+
+        let exit_code = run_headless(&spawner, child, reader_thrs, log_rx, self.restart_policy);
+        let _ = write_thr.join();
+        std::process::exit(exit_code);
+    }
+}
+
+fn hostname() -> Option<String> {
+    #[cfg(unix)]
+    {
+        std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+            .ok()
+            .map(|hostname| hostname.trim().to_string())
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME").ok()
+    }
+}
+
+fn write_session_metadata(meta: BTreeMap<String, String>) {
+    std::fs::write("session.toml", session_table(meta).to_string())
+        .expect("Failed to write session metadata (session.toml)");
+}
+
+fn session_table(meta: BTreeMap<String, String>) -> toml::Table {
+    let mut table = toml::Table::new();
+    table.insert(
+        "start_time_utc".into(),
+        chrono::Utc::now().to_rfc3339().into(),
+    );
+    if let Some(hostname) = hostname() {
+        table.insert("hostname".into(), hostname.into());
+    }
+    table.insert("pid".into(), i64::from(std::process::id()).into());
+    let working_directory = std::env::current_dir().expect("Failed to get current directory");
+    table.insert(
+        "working_directory".into(),
+        working_directory.to_string_lossy().into_owned().into(),
+    );
+    if let Ok(git_commit) = std::env::var("GIT_COMMIT") {
+        table.insert("git_commit".into(), git_commit.into());
+    }
+    let metadata: toml::Table = meta
+        .into_iter()
+        .map(|(key, value)| (key, value.into()))
+        .collect();
+    table.insert("metadata".into(), metadata.into());
+    table
+}
+
+/// Spawns the child process along with the threads that read its output.
+struct ChildSpawner {
+    flink: Option<String>,
+    log_tx: Sender<Arc<LogMessage>>,
+    write_tx: Sender<Arc<LogMessage>>,
+    current_dir: &'static Path,
+    /// The stdin of the most recently spawned child.
+    child_stdin: &'static Mutex<Option<LineWriter<ChildStdin>>>,
+}
+
+impl ChildSpawner {
+    fn spawn(&self) -> std::io::Result<(Child, [JoinHandle<()>; 2])> {
+        let mut command = Command::new(std::env::current_exe()?);
+        if let Some(flink) = &self.flink {
+            command.env(SHMEM_VAR_KEY, flink);
+        }
+        let mut child = command
+            .env(EMBEDDED_KEY, EMBEDDED_VAL)
+            .args(std::env::args().skip(1))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+        *self.child_stdin.lock() = child.stdin.take().map(LineWriter::new);
 
         let f = make_line_f(
-            log_tx.clone(),
-            write_tx.clone(),
+            self.log_tx.clone(),
+            self.write_tx.clone(),
             Level::INFO,
             "stdout",
-            current_dir,
+            self.current_dir,
         );
-        std::thread::spawn(move || {
+        let stdout_thr = std::thread::spawn(move || {
             for line in stdout.lines() {
                 let Ok(line) = line else {
                     break;
@@ -568,8 +978,14 @@ impl LumpurBuilder {
                 f(line);
             }
         });
-        let f = make_line_f(log_tx, write_tx, Level::ERROR, "stderr", current_dir);
-        std::thread::spawn(move || {
+        let f = make_line_f(
+            self.log_tx.clone(),
+            self.write_tx.clone(),
+            Level::ERROR,
+            "stderr",
+            self.current_dir,
+        );
+        let stderr_thr = std::thread::spawn(move || {
             for line in stderr.lines() {
                 let Ok(line) = line else {
                     break;
@@ -577,299 +993,207 @@ impl LumpurBuilder {
                 f(line);
             }
         });
+        Ok((child, [stdout_thr, stderr_thr]))
+    }
+}
 
-        let mut siv = cursive::default();
-        let theme = Theme::terminal_default();
-        siv.set_theme(theme);
-
-        let mut program_info_style = Style::terminal_default();
-        program_info_style.color.front = ColorType::Color(Color::Rgb(50, 200, 50));
-
-        siv.add_fullscreen_layer(
-            Layer::with_color(
-                LinearLayout::vertical()
-                    .child(TextView::new("       [PROGRAM STARTED]").style(program_info_style))
-                    .with_name(LOG_VIEW)
-                    .scrollable()
-                    .on_scroll_inner(move |scroll, _| {
-                        if scroll.is_at_bottom() {
-                            scroll.set_scroll_strategy(ScrollStrategy::StickToBottom);
-                        }
-                        EventResult::Consumed(None)
-                    })
-                    .scroll_strategy(ScrollStrategy::StickToBottom)
-                    .with_name(LOG_SCROLL_VIEW),
-                ColorStyle::terminal_default(),
-            )
-            .full_width(),
-        );
-        let extra_info_visible: &_ = Box::leak(Box::new(AtomicBool::new(false)));
-        let extra_info_callback = move |siv: &mut Cursive| {
-            let extra_info_visible = !extra_info_visible.fetch_not(Ordering::Relaxed);
-
-            siv.call_on_name(
-                LOG_SCROLL_VIEW,
-                |log_scroll_view: &mut ScrollView<LinearLayout>| {
-                    if extra_info_visible {
-                        log_scroll_view.set_scroll_strategy(ScrollStrategy::KeepRow);
-                    } else {
-                        log_scroll_view.set_scroll_strategy(ScrollStrategy::StickToBottom);
-                    }
-                },
-            );
+/// How often the headless monitor loop checks on the child when no logs are arriving.
+const HEADLESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
-            siv.call_on_name(LOG_VIEW, |log_view: &mut LinearLayout| {
-                for i in 0..log_view.len() {
-                    if let Some(_) = log_view
-                        .get_child_mut(i)
-                        .unwrap()
-                        .downcast_mut::<TextView>()
-                    {
-                        continue;
-                    }
-                    let line: &mut ThemedView<NamedView<LinearLayout>> =
-                        log_view.get_child_mut(i).unwrap().downcast_mut().unwrap();
-                    let line = &mut *line.get_inner_mut().get_mut();
-                    let top: &mut LinearLayout =
-                        line.get_child_mut(0).unwrap().downcast_mut().unwrap();
-                    let button_container: &mut LinearLayout =
-                        top.get_child_mut(0).unwrap().downcast_mut().unwrap();
-
-                    if extra_info_visible {
-                        button_container.remove_child(1);
-                        let hideable: &mut HideableView<Button> = button_container
-                            .get_child_mut(0)
-                            .unwrap()
-                            .downcast_mut()
-                            .unwrap();
-                        hideable.unhide();
-                    } else {
-                        button_container.add_child(TextView::new(" "));
-                        let hideable: &mut HideableView<Button> = button_container
-                            .get_child_mut(0)
-                            .unwrap()
-                            .downcast_mut()
-                            .unwrap();
-                        hideable.hide();
-                        if line.len() > 1 {
-                            line.remove_child(1);
-                        }
-                    }
-                }
-            });
-        };
-        siv.add_global_callback('e', extra_info_callback);
-        let mut menu_style = Style::terminal_default();
-        menu_style.color.back = ColorType::Color(Color::Rgb(80, 80, 80));
-        siv.menubar().add_leaf(
-            StyledString::styled("[E]xtras", menu_style),
-            extra_info_callback,
-        );
-        siv.add_global_callback(Key::Esc, |s| s.select_menubar());
+/// Prints logs to stdout and forwards stdin to the child until it ends for good, returning the
+/// exit code.
+fn run_headless(
+    spawner: &ChildSpawner,
+    mut child: Child,
+    mut reader_thrs: [JoinHandle<()>; 2],
+    log_rx: Receiver<Arc<LogMessage>>,
+    restart_policy: RestartPolicy,
+) -> i32 {
+    let ctrlc_count: &_ = Box::leak(Box::new(AtomicUsize::new(0)));
+    ctrlc::set_handler(move || {
+        ctrlc_count.fetch_add(1, Ordering::Relaxed);
+    })
+    .expect("Failed to set ctrl-c handler");
 
-        let clear_callback = move |siv: &mut Cursive| {
-            siv.call_on_name(LOG_VIEW, |log_view: &mut LinearLayout| {
-                log_view.clear();
-            });
-        };
-        siv.add_global_callback(Event::CtrlChar('w'), clear_callback);
-        siv.menubar().add_leaf(
-            StyledString::styled("Clear (Ctrl-W)", menu_style),
-            clear_callback,
-        );
-
-        let ctrlc_count: &_ = Box::leak(Box::new(AtomicUsize::new(0)));
-        siv.menubar()
-            .add_leaf(StyledString::styled("Quit (Ctrl-C)", menu_style), |_| {
-                ctrlc_count.fetch_add(1, Ordering::Relaxed);
-            });
-        siv.set_global_callback(Event::CtrlChar('c'), move |_| {
-            ctrlc_count.fetch_add(1, Ordering::Relaxed);
-        });
+    let child_stdin = spawner.child_stdin;
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let mut stdin = child_stdin.lock();
+            if let Some(writer) = &mut *stdin {
+                if writeln!(writer, "{line}").is_err() {
+                    *stdin = None;
+                }
+            }
+        }
+    });
 
-        siv.set_autohide_menu(false);
-
-        // We must not drop any errors past this point as the UI has spun up
-
-        let mut siv = siv.into_runner();
-        let mut last_ctrlc_count = 0;
-        siv.refresh();
-        let mut exit_code = 0;
-        let mut line_id = 0usize;
-        let mut last_message_aggregate = String::new();
-        let mut last_message_count = 0usize;
-        let mut child = Some(child);
-
-        while siv.is_running() {
-            siv.step();
-            let mut updated = false;
-            while let Ok(log) = log_rx.try_recv() {
-                let current_message_aggregate = log.aggregate();
-
-                siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
-                    if !log_view.is_empty() {
-                        if current_message_aggregate == last_message_aggregate {
-                            last_message_count += 1;
-                            let line: &mut ThemedView<NamedView<LinearLayout>> =
-                                log_view.get_child_mut(log_view.len() - 1).unwrap().downcast_mut().unwrap();
-                            let line = &mut *line.get_inner_mut().get_mut();
-                            let top: &mut LinearLayout = line.get_child_mut(0).unwrap().downcast_mut().unwrap();
-                            let repetition_text: &mut TextView =
-                                top.get_child_mut(1).unwrap().downcast_mut().unwrap();
-                            let mut style = Style::inherit_parent();
-                            style.effects.insert(Effect::Bold);
-                            repetition_text.set_content(StyledString::styled(format!(" x{: <4}", last_message_count), style));
-                            return;
-                        }
+    println!("       [PROGRAM STARTED]");
+    let mut restarts = 0usize;
+    let mut started_at = Instant::now();
+    loop {
+        if let Ok(log) = log_rx.recv_timeout(HEADLESS_POLL_INTERVAL) {
+            for log in std::iter::once(log).chain(log_rx.try_iter()) {
+                println!("{}", format_log_line(&log));
+            }
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let exit_code = if status.success() { 0 } else { 1 };
+                // Print whatever the child wrote right before it ended
+                for thr in reader_thrs {
+                    let _ = thr.join();
+                }
+                for log in log_rx.try_iter() {
+                    println!("{}", format_log_line(&log));
+                }
+                if started_at.elapsed() >= RESTART_RESET_UPTIME {
+                    restarts = 0;
+                }
+                let delay = match restart_policy.restart_delay(status, restarts) {
+                    Some(delay) if ctrlc_count.load(Ordering::Relaxed) == 0 => delay,
+                    _ => {
+                        println!("       [PROGRAM ENDED]");
+                        return exit_code;
                     }
-                    last_message_aggregate = current_message_aggregate;
-                    last_message_count = 1;
-
-                    if log_view.len() >= max_lines {
-                        log_view.remove_child(0);
+                };
+                restarts += 1;
+                println!("{}", restart_policy.restart_message(status, restarts));
+                let restart_at = Instant::now() + delay;
+                while let Some(remaining) = restart_at.checked_duration_since(Instant::now()) {
+                    if ctrlc_count.load(Ordering::Relaxed) > 0 {
+                        return exit_code;
                     }
-
-                    let mut theme = Theme::terminal_default();
-                    match &*log {
-                        LogMessage::Stdio { level: Level::ERROR, .. } | LogMessage::Standard { level: Level::ERROR, .. } => {
-                            theme.palette.set_color("Primary", Color::Rgb(240, 10, 30));
-                        }
-                        LogMessage::Stdio { level: Level::WARN, .. } | LogMessage::Standard { level: Level::WARN, .. } => {
-                            theme.palette.set_color("Primary", Color::Rgb(200, 200, 40));
-                        }
-                        _ => {}
-                    };
-                    let line_name = line_id.to_string();
-                    let line_name2 = line_name.clone();
-                    let extra_info_text = match &*log {
-                        LogMessage::Standard { target, filename, line_number, thread_name, .. } => {
-                            format!("           target: {target}    location: {filename}:{line_number}    thread: {thread_name}  ")
-                        }
-                        LogMessage::Stdio { stdio, .. } => {
-                            format!("           location: {stdio} (avoid using println or eprintln)")
-                        }
-                    };
-                    log_view.add_child(
-                        ThemedView::new(
-                            theme,
-                        LinearLayout::vertical()
-                                .child(
-                                    LinearLayout::horizontal()
-                                        .child({
-                                            let button = HideableView::new(
-                                                Button::new_raw("+", move |siv| {
-                                                siv.call_on_name(&line_name, |line: &mut LinearLayout| {
-                                                    if line.len() == 1 {
-                                                        line.add_child(
-                                                            TextView::new(extra_info_text.clone())
-                                                        );
-                                                    } else {
-                                                        line.remove_child(1);
-                                                    }
-                                                });
-                                            }));
-                                            if extra_info_visible.load(Ordering::Relaxed) {
-                                                LinearLayout::horizontal()
-                                                    .child(button)
-                                            } else {
-                                                LinearLayout::horizontal()
-                                                    .child(button.hidden())
-                                                    .child(TextView::new(" "))
-                                            }
-                                        })
-                                        .child(TextView::new("      "))
-                                        .child({
-                                            match &*log {
-                                                LogMessage::Standard { timestamp, level, fields, .. } => {
-                                                    let message = fields
-                                                        .get("message")
-                                                        .map(|v| {
-                                                            if let Some(msg) = v.as_str() {
-                                                                msg.replace('\n', "\n    ")
-                                                            } else {
-                                                                v.to_string()
-                                                            }
-                                                        })
-                                                        .unwrap_or_else(|| format!("{fields:?}"));
-                                                    TextView::new(format!("[{timestamp: >7.2}s {level: <5}] {message}"))
-                                                }
-                                                LogMessage::Stdio { level, message, ..  } => {
-                                                    TextView::new(format!("[         {level: <5}] {message}"))
-                                                }
-                                            }
-                                        })
-                                )
-                                .with_name(line_name2)
-                        )
-                    );
-                    line_id += 1;
-                });
-                updated = true;
-            }
-            if let Some(child_unwrapped) = &mut child {
-                match child_unwrapped.try_wait() {
-                    Ok(Some(status)) => {
-                        if !status.success() {
-                            exit_code = 1;
-                        }
-                        child = None;
-                        siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
-                            log_view.add_child(
-                                TextView::new("       [PROGRAM ENDED (Press Ctrl-C again)]")
-                                    .style(program_info_style),
-                            );
-                        });
-                        updated = true;
+                    std::thread::sleep(remaining.min(HEADLESS_POLL_INTERVAL));
+                }
+                match spawner.spawn() {
+                    Ok(new_child) => {
+                        (child, reader_thrs) = new_child;
+                        started_at = Instant::now();
                     }
-                    Ok(None) => {}
                     Err(e) => {
-                        exit_code = 1;
-                        child = None;
-                        siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
-                            log_view.add_child(
-                                TextView::new(format!("       [PROGRAM WAIT ERROR] {e}"))
-                                    .style(program_info_style),
-                            );
-                        });
-                        updated = true;
+                        println!("       [PROGRAM SPAWN ERROR] {e}");
+                        return 1;
                     }
                 }
             }
-            let new_ctrlc_count = ctrlc_count.load(Ordering::Relaxed);
-            if new_ctrlc_count != last_ctrlc_count {
-                last_ctrlc_count = new_ctrlc_count;
-                if let Some(child) = &mut child {
-                    if new_ctrlc_count == 1 {
-                        if let Err(e) = ctrlc_evt.set(EventState::Signaled) {
-                            eprintln!("Failed to signal ctrl-c event: {e}");
-                        }
-                    } else {
-                        exit_code = 1;
-                        if let Err(e) = child.kill() {
-                            eprintln!("Failed to kill child process: {e}");
-                        } else {
-                            eprintln!("Process killed");
-                        }
-                        siv.quit();
-                    }
-                } else {
-                    siv.quit();
-                }
+            Ok(None) => {}
+            Err(e) => {
+                println!("       [PROGRAM WAIT ERROR] {e}");
+                return 1;
             }
-            if updated {
-                siv.refresh();
+        }
+        if ctrlc_count.load(Ordering::Relaxed) > 1 {
+            if let Err(e) = child.kill() {
+                eprintln!("Failed to kill child process: {e}");
+            } else {
+                eprintln!("Process killed");
             }
+            return 1;
         }
-        // Very important to drop to return the terminal to its original state
-        drop(siv);
-        // Drop these to remove the shared memory segment file
-        drop(ctrlc_evt);
-        drop(shmem);
-        let _ = write_thr.join();
-        std::process::exit(exit_code);
     }
 }
 
 pub fn init<C: Configuration>() -> C {
     LumpurBuilder::default().init()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn restart_delay_1() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let success = ExitStatus::from_raw(0);
+        // Exit code 1
+        let failure = ExitStatus::from_raw(1 << 8);
+        let delay = Duration::from_secs(1);
+
+        assert_eq!(RestartPolicy::Never.restart_delay(failure, 0), None);
+
+        let on_failure = RestartPolicy::OnFailure {
+            max_retries: 3,
+            delay,
+        };
+        assert_eq!(on_failure.restart_delay(success, 0), None);
+        assert_eq!(on_failure.restart_delay(failure, 0), Some(delay));
+        assert_eq!(on_failure.restart_delay(failure, 1), Some(delay * 2));
+        assert_eq!(on_failure.restart_delay(failure, 2), Some(delay * 4));
+        assert_eq!(on_failure.restart_delay(failure, 3), None);
+
+        let always = RestartPolicy::Always {
+            max_retries: usize::MAX,
+            delay,
+        };
+        assert_eq!(always.restart_delay(success, 0), Some(delay));
+        assert_eq!(always.restart_delay(success, 6), Some(MAX_RESTART_DELAY));
+        assert_eq!(always.restart_delay(success, 1000), Some(MAX_RESTART_DELAY));
+
+        // A delay longer than the cap is used as is
+        let slow = RestartPolicy::Always {
+            max_retries: 2,
+            delay: MAX_RESTART_DELAY * 2,
+        };
+        assert_eq!(slow.restart_delay(failure, 1), Some(MAX_RESTART_DELAY * 2));
+    }
+
+    #[test]
+    fn session_table_1() {
+        let meta = BTreeMap::from([
+            ("pid".to_string(), "user pid".to_string()),
+            ("mission".to_string(), "dig".to_string()),
+        ]);
+        let table = session_table(meta);
+        assert_eq!(
+            table["pid"].as_integer(),
+            Some(i64::from(std::process::id()))
+        );
+        let metadata = table["metadata"].as_table().unwrap();
+        assert_eq!(metadata["pid"].as_str(), Some("user pid"));
+        assert_eq!(metadata["mission"].as_str(), Some("dig"));
+        assert!(table.contains_key("start_time_utc"));
+        assert!(table.contains_key("working_directory"));
+    }
+
+    #[test]
+    fn message_pack_round_trip() {
+        let messages = [
+            LogMessage::Stdio {
+                level: Level::WARN,
+                stdio: "stdout".into(),
+                message: "hello".into(),
+            },
+            LogMessage::Standard {
+                timestamp: 1.25,
+                level: Level::DEBUG,
+                thread_name: "main".into(),
+                target: "lunabot".into(),
+                filename: "src/main.rs".into(),
+                line_number: 42,
+                fields: BTreeMap::from([
+                    ("message".into(), "started".into()),
+                    ("count".into(), 3.into()),
+                    (
+                        "nested".into(),
+                        serde_json::json!({ "ok": true, "values": [1.5, -2] }),
+                    ),
+                ]),
+            },
+        ];
+        let path = std::env::temp_dir().join(format!("lumpur-test-{}.log", std::process::id()));
+        let mut log_file = std::fs::File::create(&path).unwrap();
+        for message in &messages {
+            rmp_serde::encode::write(&mut log_file, message).unwrap();
+        }
+        drop(log_file);
+
+        let read: Vec<_> = read_log_binary(&path).unwrap().collect();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read, messages);
+    }
+}
@@ -25,11 +25,9 @@ fn main() {
         std::thread::sleep(std::time::Duration::from_secs(3));
     });
     tracing::debug!("Hello");
-    loop {
-        warn!("{config:?}");
-        println!("{:?}", config);
-        // warn!("HGELLO");
-        std::thread::sleep(std::time::Duration::from_secs(1));
-        panic!("Panic");
-    }
+    warn!("{config:?}");
+    println!("{:?}", config);
+    // warn!("HGELLO");
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    panic!("Panic");
 }
@@ -0,0 +1,499 @@
+//! The terminal user interface, which shows the logs of the child process and forwards Ctrl-C to
+//! it through shared memory.
+
+use std::collections::VecDeque;
+use std::io::Write;
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::Instant;
+
+use cursive::event::{Event, EventResult, Key};
+use cursive::theme::{Color, ColorStyle, ColorType, Effect, Style, Theme};
+use cursive::utils::markup::StyledString;
+use cursive::view::{Nameable, Resizable, ScrollStrategy, Scrollable};
+use cursive::views::{
+    Button, Dialog, EditView, HideableView, Layer, LinearLayout, NamedView, OnEventView,
+    ScrollView, TextView, ThemedView,
+};
+use cursive::Cursive;
+use parking_lot::Mutex;
+use raw_sync::events::{EventImpl, EventInit, EventState};
+use raw_sync::Timeout;
+use shared_memory::{Shmem, ShmemConf, ShmemError};
+use tracing::Level;
+
+use crate::{on_ctrlc, ChildSpawner, LogMessage, RestartPolicy, RESTART_RESET_UPTIME};
+
+const LOG_VIEW: &str = "log_view";
+const LOG_SCROLL_VIEW: &str = "log_scroll_view";
+const STDIN_VIEW: &str = "stdin_view";
+const STDIN_HISTORY_LEN: usize = 100;
+const HEALTH_VIEW: &str = "health_view";
+
+/// Creates the shared memory event used to forward Ctrl-C to the child, returning its flink.
+pub(crate) fn create_ctrlc_event() -> (Shmem, Box<dyn EventImpl>, String) {
+    let mut shmem = None;
+    let mut flink = String::new();
+    for i in 0..1024 {
+        flink = format!(".lumpur-{i}.shmem");
+        match ShmemConf::new().size(4096).flink(&flink).create() {
+            Ok(m) => {
+                shmem = Some(m);
+                break;
+            }
+            Err(ShmemError::LinkExists) => {}
+            Err(e) => {
+                panic!("Failed to create shared memory segment: {e}");
+            }
+        };
+    }
+    let Some(shmem) = shmem else {
+        panic!("Failed to create shared memory segment. All slots occupied");
+    };
+    let (ctrlc_evt, _used_bytes) = unsafe {
+        raw_sync::events::Event::new(shmem.as_ptr(), true).expect("Failed to create ctrl-c event")
+    };
+    (shmem, ctrlc_evt, flink)
+}
+
+/// Waits for the parent to forward Ctrl-C through the shared memory segment at `flink`.
+pub(crate) fn wait_for_ctrlc(flink: &str) {
+    let shmem = match ShmemConf::new().size(4096).flink(flink).open() {
+        Ok(shmem) => shmem,
+        Err(e) => {
+            tracing::error!("Failed to open shared memory segment: {e}");
+            return;
+        }
+    };
+    let result = unsafe { raw_sync::events::Event::from_existing(shmem.as_ptr()) };
+    let (evt, _used_bytes) = match result {
+        Ok(evt) => evt,
+        Err(e) => {
+            tracing::error!("Failed to create ctrl-c event listener: {e}");
+            return;
+        }
+    };
+    if let Err(e) = evt.wait(Timeout::Infinite) {
+        tracing::error!("Failed to wait for ctrl-c event: {e}");
+    }
+    on_ctrlc();
+}
+
+/// Shows the logs of the child in the terminal until the user quits, returning the exit code.
+pub(crate) fn run_tui(
+    spawner: &ChildSpawner,
+    child: Child,
+    log_rx: Receiver<Arc<LogMessage>>,
+    health_checks: Vec<(String, &AtomicBool)>,
+    max_lines: usize,
+    restart_policy: RestartPolicy,
+    ctrlc_evt: &dyn EventImpl,
+) -> i32 {
+    let child_stdin = spawner.child_stdin;
+    let mut siv = cursive::default();
+    let theme = Theme::terminal_default();
+    siv.set_theme(theme);
+
+    let mut program_info_style = Style::terminal_default();
+    program_info_style.color.front = ColorType::Color(Color::Rgb(50, 200, 50));
+
+    let mut root_view = LinearLayout::vertical();
+    if !health_checks.is_empty() {
+        root_view.add_child(TextView::new("").with_name(HEALTH_VIEW));
+    }
+    root_view.add_child(
+        LinearLayout::vertical()
+            .child(TextView::new("       [PROGRAM STARTED]").style(program_info_style))
+            .with_name(LOG_VIEW)
+            .scrollable()
+            .on_scroll_inner(move |scroll, _| {
+                if scroll.is_at_bottom() {
+                    scroll.set_scroll_strategy(ScrollStrategy::StickToBottom);
+                }
+                EventResult::Consumed(None)
+            })
+            .scroll_strategy(ScrollStrategy::StickToBottom)
+            .with_name(LOG_SCROLL_VIEW),
+    );
+    siv.add_fullscreen_layer(
+        Layer::with_color(root_view, ColorStyle::terminal_default()).full_width(),
+    );
+    let extra_info_visible: &_ = Box::leak(Box::new(AtomicBool::new(false)));
+    let extra_info_callback = move |siv: &mut Cursive| {
+        let extra_info_visible = !extra_info_visible.fetch_not(Ordering::Relaxed);
+
+        siv.call_on_name(
+            LOG_SCROLL_VIEW,
+            |log_scroll_view: &mut ScrollView<LinearLayout>| {
+                if extra_info_visible {
+                    log_scroll_view.set_scroll_strategy(ScrollStrategy::KeepRow);
+                } else {
+                    log_scroll_view.set_scroll_strategy(ScrollStrategy::StickToBottom);
+                }
+            },
+        );
+
+        siv.call_on_name(LOG_VIEW, |log_view: &mut LinearLayout| {
+            for i in 0..log_view.len() {
+                if log_view
+                    .get_child_mut(i)
+                    .unwrap()
+                    .downcast_mut::<TextView>()
+                    .is_some()
+                {
+                    continue;
+                }
+                let line: &mut ThemedView<NamedView<LinearLayout>> =
+                    log_view.get_child_mut(i).unwrap().downcast_mut().unwrap();
+                let line = &mut *line.get_inner_mut().get_mut();
+                let top: &mut LinearLayout = line.get_child_mut(0).unwrap().downcast_mut().unwrap();
+                let button_container: &mut LinearLayout =
+                    top.get_child_mut(0).unwrap().downcast_mut().unwrap();
+
+                if extra_info_visible {
+                    button_container.remove_child(1);
+                    let hideable: &mut HideableView<Button> = button_container
+                        .get_child_mut(0)
+                        .unwrap()
+                        .downcast_mut()
+                        .unwrap();
+                    hideable.unhide();
+                } else {
+                    button_container.add_child(TextView::new(" "));
+                    let hideable: &mut HideableView<Button> = button_container
+                        .get_child_mut(0)
+                        .unwrap()
+                        .downcast_mut()
+                        .unwrap();
+                    hideable.hide();
+                    if line.len() > 1 {
+                        line.remove_child(1);
+                    }
+                }
+            }
+        });
+    };
+    siv.add_global_callback('e', extra_info_callback);
+    let mut menu_style = Style::terminal_default();
+    menu_style.color.back = ColorType::Color(Color::Rgb(80, 80, 80));
+    siv.menubar().add_leaf(
+        StyledString::styled("[E]xtras", menu_style),
+        extra_info_callback,
+    );
+    siv.add_global_callback(Key::Esc, |s| s.select_menubar());
+
+    // The sent commands, and the index of the command currently being recalled
+    let stdin_history: &_ = Box::leak(Box::new(Mutex::new((VecDeque::<String>::new(), 0usize))));
+    let stdin_callback = move |siv: &mut Cursive| {
+        if siv.find_name::<EditView>(STDIN_VIEW).is_some() {
+            return;
+        }
+        {
+            let mut history = stdin_history.lock();
+            history.1 = history.0.len();
+        }
+        siv.add_layer(
+            Dialog::around(
+                OnEventView::new(
+                    EditView::new()
+                        .on_submit(move |siv, text| {
+                            siv.pop_layer();
+                            if text.is_empty() {
+                                return;
+                            }
+                            let mut stdin = child_stdin.lock();
+                            if let Some(writer) = &mut *stdin {
+                                if writeln!(writer, "{text}").is_err() {
+                                    *stdin = None;
+                                }
+                            }
+                            let mut history = stdin_history.lock();
+                            if history.0.len() >= STDIN_HISTORY_LEN {
+                                history.0.pop_front();
+                            }
+                            history.0.push_back(text.to_string());
+                        })
+                        .with_name(STDIN_VIEW)
+                        .fixed_width(60),
+                )
+                .on_event(Key::Up, move |siv| {
+                    let mut history = stdin_history.lock();
+                    if history.1 == 0 {
+                        return;
+                    }
+                    history.1 -= 1;
+                    let text = history.0[history.1].clone();
+                    siv.call_on_name(STDIN_VIEW, |view: &mut EditView| {
+                        let _ = view.set_content(text);
+                    });
+                })
+                .on_event(Key::Down, move |siv| {
+                    let mut history = stdin_history.lock();
+                    if history.1 >= history.0.len() {
+                        return;
+                    }
+                    history.1 += 1;
+                    let text = history.0.get(history.1).cloned().unwrap_or_default();
+                    siv.call_on_name(STDIN_VIEW, |view: &mut EditView| {
+                        let _ = view.set_content(text);
+                    });
+                }),
+            )
+            .title("Send to stdin")
+            .dismiss_button("Cancel"),
+        );
+    };
+    siv.add_global_callback(':', stdin_callback);
+    siv.menubar().add_leaf(
+        StyledString::styled("Stdin (:)", menu_style),
+        stdin_callback,
+    );
+
+    let clear_callback = move |siv: &mut Cursive| {
+        siv.call_on_name(LOG_VIEW, |log_view: &mut LinearLayout| {
+            log_view.clear();
+        });
+    };
+    siv.add_global_callback(Event::CtrlChar('w'), clear_callback);
+    siv.menubar().add_leaf(
+        StyledString::styled("Clear (Ctrl-W)", menu_style),
+        clear_callback,
+    );
+
+    let ctrlc_count: &_ = Box::leak(Box::new(AtomicUsize::new(0)));
+    siv.menubar()
+        .add_leaf(StyledString::styled("Quit (Ctrl-C)", menu_style), |_| {
+            ctrlc_count.fetch_add(1, Ordering::Relaxed);
+        });
+    siv.set_global_callback(Event::CtrlChar('c'), move |_| {
+        ctrlc_count.fetch_add(1, Ordering::Relaxed);
+    });
+
+    siv.set_autohide_menu(false);
+
+    // We must not drop any errors past this point as the UI has spun up
+
+    let mut siv = siv.into_runner();
+    let mut last_ctrlc_count = 0;
+    siv.refresh();
+    let mut exit_code = 0;
+    let mut line_id = 0usize;
+    let mut last_message_aggregate = String::new();
+    let mut last_message_count = 0usize;
+    let mut child = Some(child);
+    let mut restarts = 0usize;
+    let mut restart_at: Option<Instant> = None;
+    let mut started_at = Instant::now();
+    let mut healthy_style = Style::terminal_default();
+    healthy_style.color.front = ColorType::Color(Color::Rgb(50, 200, 50));
+    let mut unhealthy_style = Style::terminal_default();
+    unhealthy_style.color.front = ColorType::Color(Color::Rgb(240, 10, 30));
+    let mut last_health = vec![];
+
+    while siv.is_running() {
+        siv.step();
+        let mut updated = false;
+        let health: Vec<bool> = health_checks
+            .iter()
+            .map(|(_, healthy)| healthy.load(Ordering::Relaxed))
+            .collect();
+        if health != last_health {
+            let mut header = StyledString::plain("       ");
+            for ((name, _), &is_healthy) in health_checks.iter().zip(&health) {
+                if is_healthy {
+                    header.append_styled(format!("● {name}  "), healthy_style);
+                } else {
+                    header.append_styled(format!("✗ {name}  "), unhealthy_style);
+                }
+            }
+            siv.call_on_name(HEALTH_VIEW, |health_view: &mut TextView| {
+                health_view.set_content(header);
+            });
+            last_health = health;
+            updated = true;
+        }
+        while let Ok(log) = log_rx.try_recv() {
+            let current_message_aggregate = log.aggregate();
+
+            siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
+                if !log_view.is_empty() && current_message_aggregate == last_message_aggregate {
+                    last_message_count += 1;
+                    let line: &mut ThemedView<NamedView<LinearLayout>> =
+                        log_view.get_child_mut(log_view.len() - 1).unwrap().downcast_mut().unwrap();
+                    let line = &mut *line.get_inner_mut().get_mut();
+                    let top: &mut LinearLayout = line.get_child_mut(0).unwrap().downcast_mut().unwrap();
+                    let repetition_text: &mut TextView =
+                        top.get_child_mut(1).unwrap().downcast_mut().unwrap();
+                    let mut style = Style::inherit_parent();
+                    style.effects.insert(Effect::Bold);
+                    repetition_text.set_content(StyledString::styled(format!(" x{: <4}", last_message_count), style));
+                    return;
+                }
+                last_message_aggregate = current_message_aggregate;
+                last_message_count = 1;
+
+                if log_view.len() >= max_lines {
+                    log_view.remove_child(0);
+                }
+
+                let mut theme = Theme::terminal_default();
+                match &*log {
+                    LogMessage::Stdio { level: Level::ERROR, .. } | LogMessage::Standard { level: Level::ERROR, .. } => {
+                        theme.palette.set_color("Primary", Color::Rgb(240, 10, 30));
+                    }
+                    LogMessage::Stdio { level: Level::WARN, .. } | LogMessage::Standard { level: Level::WARN, .. } => {
+                        theme.palette.set_color("Primary", Color::Rgb(200, 200, 40));
+                    }
+                    _ => {}
+                };
+                let line_name = line_id.to_string();
+                let line_name2 = line_name.clone();
+                let extra_info_text = match &*log {
+                    LogMessage::Standard { target, filename, line_number, thread_name, .. } => {
+                        format!("           target: {target}    location: {filename}:{line_number}    thread: {thread_name}  ")
+                    }
+                    LogMessage::Stdio { stdio, .. } => {
+                        format!("           location: {stdio} (avoid using println or eprintln)")
+                    }
+                };
+                log_view.add_child(
+                    ThemedView::new(
+                        theme,
+                    LinearLayout::vertical()
+                            .child(
+                                LinearLayout::horizontal()
+                                    .child({
+                                        let button = HideableView::new(
+                                            Button::new_raw("+", move |siv| {
+                                            siv.call_on_name(&line_name, |line: &mut LinearLayout| {
+                                                if line.len() == 1 {
+                                                    line.add_child(
+                                                        TextView::new(extra_info_text.clone())
+                                                    );
+                                                } else {
+                                                    line.remove_child(1);
+                                                }
+                                            });
+                                        }));
+                                        if extra_info_visible.load(Ordering::Relaxed) {
+                                            LinearLayout::horizontal()
+                                                .child(button)
+                                        } else {
+                                            LinearLayout::horizontal()
+                                                .child(button.hidden())
+                                                .child(TextView::new(" "))
+                                        }
+                                    })
+                                    .child(TextView::new("      "))
+                                    .child({
+                                        match &*log {
+                                            LogMessage::Standard { timestamp, level, fields, .. } => {
+                                                let message = fields
+                                                    .get("message")
+                                                    .map(|v| {
+                                                        if let Some(msg) = v.as_str() {
+                                                            msg.replace('\n', "\n    ")
+                                                        } else {
+                                                            v.to_string()
+                                                        }
+                                                    })
+                                                    .unwrap_or_else(|| format!("{fields:?}"));
+                                                TextView::new(format!("[{timestamp: >7.2}s {level: <5}] {message}"))
+                                            }
+                                            LogMessage::Stdio { level, message, ..  } => {
+                                                TextView::new(format!("[         {level: <5}] {message}"))
+                                            }
+                                        }
+                                    })
+                            )
+                            .with_name(line_name2)
+                    )
+                );
+                line_id += 1;
+            });
+            updated = true;
+        }
+        if let Some(child_unwrapped) = &mut child {
+            match child_unwrapped.try_wait() {
+                Ok(Some(status)) => {
+                    exit_code = if status.success() { 0 } else { 1 };
+                    child = None;
+                    if started_at.elapsed() >= RESTART_RESET_UPTIME {
+                        restarts = 0;
+                    }
+                    let message = match restart_policy.restart_delay(status, restarts) {
+                        Some(delay) if last_ctrlc_count == 0 => {
+                            restarts += 1;
+                            restart_at = Some(Instant::now() + delay);
+                            restart_policy.restart_message(status, restarts)
+                        }
+                        _ => "       [PROGRAM ENDED (Press Ctrl-C again)]".into(),
+                    };
+                    siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
+                        log_view.add_child(TextView::new(message).style(program_info_style));
+                    });
+                    updated = true;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    exit_code = 1;
+                    child = None;
+                    siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
+                        log_view.add_child(
+                            TextView::new(format!("       [PROGRAM WAIT ERROR] {e}"))
+                                .style(program_info_style),
+                        );
+                    });
+                    updated = true;
+                }
+            }
+        }
+        if child.is_none() && restart_at.is_some_and(|at| at <= Instant::now()) {
+            restart_at = None;
+            let message = match spawner.spawn() {
+                Ok((new_child, _)) => {
+                    child = Some(new_child);
+                    started_at = Instant::now();
+                    "       [PROGRAM RESTARTED]".to_string()
+                }
+                Err(e) => {
+                    exit_code = 1;
+                    format!("       [PROGRAM SPAWN ERROR (Press Ctrl-C again)] {e}")
+                }
+            };
+            siv.call_on_name::<LinearLayout, _, _>(LOG_VIEW, |log_view| {
+                log_view.add_child(TextView::new(message).style(program_info_style));
+            });
+            updated = true;
+        }
+        let new_ctrlc_count = ctrlc_count.load(Ordering::Relaxed);
+        if new_ctrlc_count != last_ctrlc_count {
+            last_ctrlc_count = new_ctrlc_count;
+            if let Some(child) = &mut child {
+                if new_ctrlc_count == 1 {
+                    if let Err(e) = ctrlc_evt.set(EventState::Signaled) {
+                        eprintln!("Failed to signal ctrl-c event: {e}");
+                    }
+                } else {
+                    exit_code = 1;
+                    if let Err(e) = child.kill() {
+                        eprintln!("Failed to kill child process: {e}");
+                    } else {
+                        eprintln!("Process killed");
+                    }
+                    siv.quit();
+                }
+            } else {
+                siv.quit();
+            }
+        }
+        if updated {
+            siv.refresh();
+        }
+    }
+    // Very important to drop to return the terminal to its original state
+    drop(siv);
+    exit_code
+}
@@ -22,7 +22,11 @@ mod depth2pcl;
 mod grad2obstacle;
 mod height2grad;
 mod pcl2height;
+mod pgm;
+mod raycast;
 pub use clustering::Clusterer;
+pub use pgm::{occupancy_to_pgm_bytes, write_occupancy_pgm};
+pub use raycast::ray_cast;
 
 mod expand_obstacles;
 use expand_obstacles::ExpandObstacles;
@@ -230,10 +234,27 @@ pub struct ThalassicBuilder {
     pub heightmap_dimensions: Vector2<NonZeroU32>,
     pub cell_size: f32,
     pub max_point_count: NonZeroU32,
+    /// See [`ThalassicPipeline::set_temporal_filter`].
+    pub temporal_filter_alpha: Option<f32>,
 }
 
 impl ThalassicBuilder {
+    /// Enables the temporal filter with the given `alpha`. See [`ThalassicPipeline::set_temporal_filter`].
+    ///
+    /// # Panics
+    /// Panics if `alpha` is not in the range `(0, 1]`.
+    pub fn with_temporal_filter(mut self, alpha: f32) -> Self {
+        check_temporal_filter_alpha(alpha);
+        self.temporal_filter_alpha = Some(alpha);
+        self
+    }
+
+    /// # Panics
+    /// Panics if `temporal_filter_alpha` is not in the range `(0, 1]`.
     pub fn build(self) -> ThalassicPipeline {
+        if let Some(alpha) = self.temporal_filter_alpha {
+            check_temporal_filter_alpha(alpha);
+        }
         let max_triangle_count =
             (self.heightmap_dimensions.x.get() - 1) * (self.heightmap_dimensions.y.get() - 1) * 2;
         let max_triangle_count = NonZeroU32::new(max_triangle_count)
@@ -319,12 +340,15 @@ impl ThalassicBuilder {
             new_max_gradient: Some(45.0f32.to_radians()),
             expander_input_grp_zeros: vec![0; cell_count.get() as usize * 2].into_boxed_slice(),
             cell_size: self.cell_size,
+            temporal_filter_alpha: self.temporal_filter_alpha,
+            obstacle_confidence: vec![0.0; cell_count.get() as usize].into_boxed_slice(),
+            filtered_obstacles: vec![Occupancy::FREE; cell_count.get() as usize].into_boxed_slice(),
         }
     }
 }
 
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
 pub struct Occupancy(u32);
 
 impl Occupancy {
@@ -352,6 +376,48 @@ pub struct ThalassicPipeline {
     new_max_gradient: Option<f32>,
     expander_input_grp_zeros: Box<[u32]>,
     cell_size: f32,
+    temporal_filter_alpha: Option<f32>,
+    /// The exponential moving average of the obstacle map, in the range `0.0..=1.0`.
+    obstacle_confidence: Box<[f32]>,
+    /// The obstacle map produced by the temporal filter in the previous frame.
+    filtered_obstacles: Box<[Occupancy]>,
+}
+
+fn check_temporal_filter_alpha(alpha: f32) {
+    assert!(
+        alpha > 0.0 && alpha <= 1.0,
+        "alpha must be in the range (0, 1]"
+    );
+}
+
+/// Blends `obstacles` into the running `confidence` of each cell, then replaces each cell of
+/// `obstacles` with its filtered value.
+///
+/// Cells that are occupied in both the new sample and the filtered map keep their new value,
+/// while cells that are only held occupied by their history keep their value from `previous`.
+/// `previous` is then updated to the filtered map.
+fn apply_temporal_filter(
+    alpha: f32,
+    obstacles: &mut [Occupancy],
+    confidence: &mut [f32],
+    previous: &mut [Occupancy],
+) {
+    for ((occupancy, confidence), previous) in obstacles
+        .iter_mut()
+        .zip(confidence.iter_mut())
+        .zip(previous.iter_mut())
+    {
+        let new = if occupancy.occupied() { 1.0 } else { 0.0 };
+        *confidence = alpha * new + (1.0 - alpha) * *confidence;
+        if *confidence < 0.5 {
+            *occupancy = Occupancy::FREE;
+        } else if !occupancy.occupied() {
+            // The confidence only drops when the sample is free, so this cell was occupied
+            // in the previous filtered map
+            *occupancy = *previous;
+        }
+        *previous = *occupancy;
+    }
 }
 
 impl ThalassicPipeline {
@@ -472,6 +538,15 @@ impl ThalassicPipeline {
             .0
             .read(bytemuck::cast_slice_mut(out_expanded_obstacles));
 
+        if let Some(alpha) = self.temporal_filter_alpha {
+            apply_temporal_filter(
+                alpha,
+                out_expanded_obstacles,
+                &mut self.obstacle_confidence,
+                &mut self.filtered_obstacles,
+            );
+        }
+
         self.bind_grps = Some((
             height_grp,
             pcl_grp,
@@ -488,4 +563,54 @@ impl ThalassicPipeline {
     pub fn set_radius(&mut self, radius: f32) {
         self.new_radius_cells = Some(radius / self.cell_size);
     }
+
+    /// Smooths the obstacle map over time with an exponential moving average.
+    ///
+    /// Each cell's confidence is updated as `alpha * new + (1 - alpha) * previous`,
+    /// and a cell is reported as occupied while its confidence is at least `0.5`.
+    /// Smaller values of `alpha` filter out more noise, but react more slowly
+    /// to real changes. Passing `None` disables the filter and clears its history.
+    ///
+    /// # Panics
+    /// Panics if `alpha` is not in the range `(0, 1]`.
+    pub fn set_temporal_filter(&mut self, alpha: Option<f32>) {
+        if let Some(alpha) = alpha {
+            check_temporal_filter_alpha(alpha);
+        } else {
+            self.obstacle_confidence.fill(0.0);
+            self.filtered_obstacles.fill(Occupancy::FREE);
+        }
+        self.temporal_filter_alpha = alpha;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_temporal_filter, Occupancy};
+
+    #[test]
+    fn temporal_filter_1() {
+        // Cell 0 sees a single noisy frame, while cell 1 sees a persistent obstacle that
+        // briefly disappears
+        let frames = [
+            [Occupancy(1), Occupancy(2)],
+            [Occupancy::FREE, Occupancy(2)],
+            [Occupancy::FREE, Occupancy(3)],
+            [Occupancy::FREE, Occupancy(3)],
+            [Occupancy::FREE, Occupancy::FREE],
+        ];
+        let expected = [
+            [Occupancy::FREE, Occupancy::FREE],
+            [Occupancy::FREE, Occupancy(2)],
+            [Occupancy::FREE, Occupancy(3)],
+            [Occupancy::FREE, Occupancy(3)],
+            [Occupancy::FREE, Occupancy(3)],
+        ];
+        let mut confidence = [0.0; 2];
+        let mut previous = [Occupancy::FREE; 2];
+        for (mut frame, expected) in frames.into_iter().zip(expected) {
+            apply_temporal_filter(0.3, &mut frame, &mut confidence, &mut previous);
+            assert_eq!(frame, expected);
+        }
+    }
 }
@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use crate::Occupancy;
+
+/// Encodes an occupancy grid as a binary (P5) PGM image, with free cells in white
+/// and occupied cells in black.
+///
+/// Cells are laid out row by row, so `(x, y)` is at index `y * width + x`, and
+/// becomes the pixel at column `x` and row `y` of the image.
+pub fn occupancy_to_pgm_bytes(occupancy: &[Occupancy], width: u32, height: u32) -> Vec<u8> {
+    let cell_count = width as usize * height as usize;
+    assert_eq!(
+        occupancy.len(),
+        cell_count,
+        "occupancy length does not match the grid dimensions"
+    );
+    let mut bytes = format!("P5\n{width} {height}\n255\n").into_bytes();
+    bytes.reserve(cell_count);
+    bytes.extend(
+        occupancy
+            .iter()
+            .map(|occupancy| if occupancy.occupied() { 0 } else { 255 }),
+    );
+    bytes
+}
+
+/// Writes an occupancy grid to `path` as a binary PGM image.
+///
+/// See [`occupancy_to_pgm_bytes`] for the image layout.
+pub fn write_occupancy_pgm(
+    path: &Path,
+    occupancy: &[Occupancy],
+    width: u32,
+    height: u32,
+) -> std::io::Result<()> {
+    std::fs::write(path, occupancy_to_pgm_bytes(occupancy, width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::occupancy_to_pgm_bytes;
+    use crate::Occupancy;
+
+    #[test]
+    fn pgm_bytes_1() {
+        let (width, height) = (3, 2);
+        let mut occupancy = vec![Occupancy::FREE; 6];
+        // (2, 0) and (0, 1)
+        occupancy[2] = Occupancy(1);
+        occupancy[width] = Occupancy(1);
+
+        let bytes = occupancy_to_pgm_bytes(&occupancy, width as u32, height);
+        let header = b"P5\n3 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], [255, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    #[should_panic = "occupancy length does not match the grid dimensions"]
+    fn pgm_bytes_length_mismatch() {
+        occupancy_to_pgm_bytes(&[Occupancy::FREE; 5], 3, 2);
+    }
+}
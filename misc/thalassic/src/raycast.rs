@@ -0,0 +1,122 @@
+use crate::Occupancy;
+
+/// Walks the grid from `start` to `end` (inclusive) using Bresenham's line algorithm,
+/// returning the first occupied cell along the way, or `None` if the path is clear.
+///
+/// Cells are laid out row by row, so `(x, y)` is at index `y * grid_width + x`.
+/// Cells outside of the grid are treated as occupied.
+pub fn ray_cast(
+    occupancy: &[Occupancy],
+    grid_width: u32,
+    grid_height: u32,
+    start: (u32, u32),
+    end: (u32, u32),
+) -> Option<(u32, u32)> {
+    let (mut x, mut y) = (start.0 as i64, start.1 as i64);
+    let (end_x, end_y) = (end.0 as i64, end.1 as i64);
+    let dx = (end_x - x).abs();
+    let dy = -(end_y - y).abs();
+    let step_x = if x < end_x { 1 } else { -1 };
+    let step_y = if y < end_y { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        let cell = (x as u32, y as u32);
+        if cell.0 >= grid_width || cell.1 >= grid_height {
+            return Some(cell);
+        }
+        match occupancy.get(cell.1 as usize * grid_width as usize + cell.0 as usize) {
+            Some(occupancy) if !occupancy.occupied() => {}
+            _ => return Some(cell),
+        }
+        if x == end_x && y == end_y {
+            return None;
+        }
+        let error2 = 2 * error;
+        if error2 >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if error2 <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ray_cast;
+    use crate::Occupancy;
+
+    const WIDTH: u32 = 5;
+    const HEIGHT: u32 = 5;
+
+    fn grid(occupied: &[(u32, u32)]) -> Vec<Occupancy> {
+        let mut grid = vec![Occupancy::FREE; (WIDTH * HEIGHT) as usize];
+        for &(x, y) in occupied {
+            grid[(y * WIDTH + x) as usize] = Occupancy(1);
+        }
+        grid
+    }
+
+    fn cast(occupied: &[(u32, u32)], start: (u32, u32), end: (u32, u32)) -> Option<(u32, u32)> {
+        ray_cast(&grid(occupied), WIDTH, HEIGHT, start, end)
+    }
+
+    #[test]
+    fn start_is_end() {
+        assert_eq!(cast(&[], (2, 2), (2, 2)), None);
+        assert_eq!(cast(&[(2, 2)], (2, 2), (2, 2)), Some((2, 2)));
+    }
+
+    #[test]
+    fn horizontal() {
+        assert_eq!(cast(&[(3, 1)], (0, 2), (4, 2)), None);
+        assert_eq!(cast(&[(3, 2)], (0, 2), (4, 2)), Some((3, 2)));
+    }
+
+    #[test]
+    fn vertical() {
+        assert_eq!(cast(&[(1, 3)], (2, 0), (2, 4)), None);
+        assert_eq!(cast(&[(2, 3)], (2, 0), (2, 4)), Some((2, 3)));
+    }
+
+    #[test]
+    fn steep() {
+        // The line visits (0, 0), (0, 1), (1, 2), (1, 3) and (1, 4)
+        assert_eq!(cast(&[(0, 2), (1, 1)], (0, 0), (1, 4)), None);
+        assert_eq!(cast(&[(1, 2)], (0, 0), (1, 4)), Some((1, 2)));
+        assert_eq!(cast(&[(0, 1)], (0, 0), (1, 4)), Some((0, 1)));
+    }
+
+    #[test]
+    fn reversed() {
+        // The obstacle closest to `start` is returned
+        assert_eq!(cast(&[(1, 2), (3, 2)], (4, 2), (0, 2)), Some((3, 2)));
+        assert_eq!(cast(&[(1, 2), (3, 2)], (0, 2), (4, 2)), Some((1, 2)));
+        assert_eq!(cast(&[(2, 3)], (2, 4), (2, 0)), Some((2, 3)));
+        assert_eq!(cast(&[(1, 1), (3, 3)], (4, 4), (0, 0)), Some((3, 3)));
+        assert_eq!(cast(&[(1, 3), (3, 1)], (4, 4), (0, 0)), None);
+    }
+
+    #[test]
+    fn obstacle_at_ends() {
+        assert_eq!(cast(&[(0, 0), (4, 4)], (0, 0), (4, 4)), Some((0, 0)));
+        assert_eq!(cast(&[(4, 4)], (0, 0), (4, 4)), Some((4, 4)));
+    }
+
+    #[test]
+    fn outside_grid() {
+        assert_eq!(cast(&[], (10, 10), (0, 0)), Some((10, 10)));
+        assert_eq!(cast(&[], (0, 0), (WIDTH, 0)), Some((WIDTH, 0)));
+        assert_eq!(cast(&[], (0, 0), (0, HEIGHT)), Some((0, HEIGHT)));
+        // The first cell outside of the grid is returned
+        assert_eq!(cast(&[], (0, 0), (WIDTH + 2, 0)), Some((WIDTH, 0)));
+        // An occupancy slice that is too short is treated as occupied past its end
+        assert_eq!(
+            ray_cast(&[Occupancy::FREE; 3], WIDTH, HEIGHT, (0, 0), (4, 0)),
+            Some((3, 0))
+        );
+    }
+}